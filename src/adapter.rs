@@ -0,0 +1,9 @@
+pub mod bm25;
+pub mod hnsw;
+pub mod memory;
+pub mod openai;
+pub mod prompt_files;
+pub mod quantization;
+pub mod sse;
+pub mod tokenizer;
+pub mod weaviate;
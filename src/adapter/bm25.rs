@@ -0,0 +1,241 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Error;
+use std::sync::RwLock;
+
+use rust_stemmers::{Algorithm, Stemmer};
+use uuid::Uuid;
+
+use crate::domain::document::Chunk;
+use crate::domain::embedding::ScoredChunk;
+use crate::domain::filter::{Filter, Metadata};
+use crate::domain::lexical::LexicalIndex;
+
+struct Entry {
+    terms: HashMap<String, u32>,
+    len: usize,
+    metadata: Metadata,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<Uuid, Entry>,
+    postings: HashMap<String, HashSet<Uuid>>,
+    total_len: usize,
+}
+
+// BM25 в памяти процесса со стеммингом русского и английского
+pub struct Bm25Index {
+    pub k1: f32,
+    pub b: f32,
+    russian: Stemmer,
+    english: Stemmer,
+    inner: RwLock<Inner>,
+}
+
+impl Default for Bm25Index {
+    fn default() -> Self {
+        Self::new(1.2, 0.75)
+    }
+}
+
+impl Bm25Index {
+    pub fn new(k1: f32, b: f32) -> Self {
+        Self {
+            k1,
+            b,
+            russian: Stemmer::create(Algorithm::Russian),
+            english: Stemmer::create(Algorithm::English),
+            inner: RwLock::new(Inner::default()),
+        }
+    }
+
+    // Слова приводятся к основе по алфавиту, в котором написаны.
+    // Токены с цифрами и подчёркиваниями (коды ошибок, идентификаторы) не стеммятся.
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .filter(|token| !token.is_empty())
+            .map(|token| {
+                let token = token.to_lowercase();
+                if token.chars().any(|c| c.is_ascii_digit() || c == '_') {
+                    token
+                } else if token.chars().any(is_cyrillic) {
+                    self.russian.stem(&token).into_owned()
+                } else {
+                    self.english.stem(&token).into_owned()
+                }
+            })
+            .collect()
+    }
+
+    fn remove(inner: &mut Inner, chunk_id: Uuid) {
+        let Some(entry) = inner.entries.remove(&chunk_id) else {
+            return;
+        };
+        inner.total_len -= entry.len;
+        for term in entry.terms.keys() {
+            if let Some(chunks) = inner.postings.get_mut(term) {
+                chunks.remove(&chunk_id);
+                if chunks.is_empty() {
+                    inner.postings.remove(term);
+                }
+            }
+        }
+    }
+}
+
+fn is_cyrillic(c: char) -> bool {
+    matches!(c, '\u{0400}'..='\u{04FF}')
+}
+
+#[async_trait::async_trait]
+impl LexicalIndex for Bm25Index {
+    async fn save(&self, chunk: &Chunk) -> Result<(), Error> {
+        let tokens = self.tokenize(&chunk.text);
+        let mut terms = HashMap::new();
+        for token in &tokens {
+            *terms.entry(token.clone()).or_insert(0) += 1;
+        }
+
+        let mut inner = self.inner.write().unwrap();
+        Self::remove(&mut inner, chunk.id);
+        for term in terms.keys() {
+            inner
+                .postings
+                .entry(term.clone())
+                .or_default()
+                .insert(chunk.id);
+        }
+        inner.total_len += tokens.len();
+        inner.entries.insert(
+            chunk.id,
+            Entry {
+                terms,
+                len: tokens.len(),
+                metadata: chunk.metadata.clone(),
+            },
+        );
+        Ok(())
+    }
+
+    async fn delete(&self, chunk_id: Uuid) -> Result<(), Error> {
+        Self::remove(&mut self.inner.write().unwrap(), chunk_id);
+        Ok(())
+    }
+
+    async fn search_text<'a>(
+        &self,
+        text: &str,
+        top_k: usize,
+        filter: Option<&'a Filter>,
+    ) -> Result<Vec<ScoredChunk>, Error> {
+        let query: HashSet<String> = self.tokenize(text).into_iter().collect();
+        let inner = self.inner.read().unwrap();
+        if inner.entries.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let n = inner.entries.len() as f32;
+        let avg_len = inner.total_len as f32 / n;
+        let mut scores: HashMap<Uuid, f32> = HashMap::new();
+
+        for term in &query {
+            let Some(chunks) = inner.postings.get(term) else {
+                continue;
+            };
+            let df = chunks.len() as f32;
+            let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+
+            for chunk_id in chunks {
+                let entry = &inner.entries[chunk_id];
+                if filter.is_some_and(|f| !f.matches(&entry.metadata)) {
+                    continue;
+                }
+                let tf = entry.terms[term] as f32;
+                let norm = self.k1 * (1.0 - self.b + self.b * entry.len as f32 / avg_len);
+                *scores.entry(*chunk_id).or_default() += idf * tf * (self.k1 + 1.0) / (tf + norm);
+            }
+        }
+
+        let mut found: Vec<ScoredChunk> = scores
+            .into_iter()
+            .map(|(chunk_id, score)| ScoredChunk { chunk_id, score })
+            .collect();
+        found.sort_by(|a, b| b.score.total_cmp(&a.score));
+        found.truncate(top_k);
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn index(texts: &[&str]) -> (Bm25Index, Vec<Uuid>) {
+        let index = Bm25Index::default();
+        let doc_id = Uuid::new_v4();
+        let mut ids = Vec::new();
+        for text in texts {
+            let chunk = Chunk::new(doc_id, text.to_string());
+            ids.push(chunk.id);
+            index.save(&chunk).await.unwrap();
+        }
+        (index, ids)
+    }
+
+    #[tokio::test]
+    async fn test_stemming_matches_word_forms() {
+        let (index, ids) = index(&[
+            "Мороз и солнце; день чудесный!",
+            "Луна, как бледное пятно",
+            "The service keeps running after restarts",
+        ])
+        .await;
+
+        let found = index.search_text("морозы", 3, None).await.unwrap();
+        assert_eq!(found[0].chunk_id, ids[0]);
+
+        let found = index
+            .search_text("service restarted", 3, None)
+            .await
+            .unwrap();
+        assert_eq!(found[0].chunk_id, ids[2]);
+        assert_eq!(found.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_identifiers_match_exactly() {
+        let (index, ids) = index(&[
+            "Ошибка ERR_CONN_RESET при подключении",
+            "Ошибка E1042 при сохранении",
+            "Ошибка при подключении к базе",
+        ])
+        .await;
+
+        let found = index
+            .search_text("что значит E1042?", 3, None)
+            .await
+            .unwrap();
+
+        assert_eq!(found[0].chunk_id, ids[1]);
+        assert!(found[0].score > found.get(1).map_or(0.0, |c| c.score));
+        let found = index.search_text("err_conn_reset", 3, None).await.unwrap();
+        assert_eq!(found[0].chunk_id, ids[0]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_and_filter() {
+        let (index, ids) = index(&["красная рыба", "красная икра"]).await;
+
+        index.delete(ids[0]).await.unwrap();
+        let found = index.search_text("красный", 5, None).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].chunk_id, ids[1]);
+
+        let other_doc = Filter::document(Uuid::new_v4());
+        let found = index
+            .search_text("красный", 5, Some(&other_doc))
+            .await
+            .unwrap();
+        assert!(found.is_empty());
+    }
+}
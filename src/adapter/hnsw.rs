@@ -0,0 +1,961 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::RwLock;
+
+use memmap2::Mmap;
+use uuid::Uuid;
+
+use crate::adapter::quantization::normalize;
+use crate::domain::embedding::{
+    ChunkEmbending, ChunkEmbendingRepo, Metric, ScoredChunk, VectorSearcher,
+};
+use crate::domain::filter::{Filter, Metadata};
+
+const MAGIC: &[u8; 4] = b"HNSW";
+const FORMAT_VERSION: u32 = 2;
+const NO_ENTRY_POINT: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HnswParams {
+    // Число связей узла на верхних слоях (на нулевом — вдвое больше)
+    pub m: usize,
+    pub ef_construction: usize,
+    pub ef_search: usize,
+}
+
+impl Default for HnswParams {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 200,
+            ef_search: 64,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Candidate {
+    distance: f32,
+    node: u32,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.node.cmp(&other.node))
+    }
+}
+
+// Векторы лежат одним плоским массивом: в памяти или прямо в отображённом файле.
+// Первая запись после открытия файла копирует их в память.
+enum Vectors {
+    Owned(Vec<f32>),
+    Mapped {
+        map: Mmap,
+        offset: usize,
+        len: usize,
+    },
+}
+
+impl Vectors {
+    fn as_slice(&self) -> &[f32] {
+        match self {
+            Vectors::Owned(vec) => vec,
+            Vectors::Mapped { map, offset, len } => {
+                let bytes = &map[*offset..*offset + len * size_of::<f32>()];
+                // SAFETY: смещение выровнено на 4 при записи, начало mmap выровнено на страницу.
+                // Файл пишется в little-endian, а Mapped создаётся только на little-endian хостах,
+                // поэтому байты файла совпадают с представлением f32 в памяти.
+                unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const f32, *len) }
+            }
+        }
+    }
+
+    fn push(&mut self, vec: &[f32]) {
+        if let Vectors::Mapped { .. } = self {
+            *self = Vectors::Owned(self.as_slice().to_vec());
+        }
+        if let Vectors::Owned(data) = self {
+            data.extend_from_slice(vec);
+        }
+    }
+}
+
+struct Node {
+    chunk_id: Uuid,
+    embending_id: Uuid,
+    deleted: bool,
+    metadata: Metadata,
+    // Списки соседей по слоям, от нулевого до уровня узла
+    neighbors: Vec<Vec<u32>>,
+}
+
+struct Graph {
+    metric: Metric,
+    params: HnswParams,
+    model_id: String,
+    dim: usize,
+    nodes: Vec<Node>,
+    vectors: Vectors,
+    by_chunk: HashMap<Uuid, u32>,
+    entry_point: Option<u32>,
+    max_level: usize,
+    rng: u64,
+}
+
+impl Graph {
+    fn vector(&self, node: u32) -> &[f32] {
+        let start = node as usize * self.dim;
+        &self.vectors.as_slice()[start..start + self.dim]
+    }
+
+    fn distance(&self, query: &[f32], node: u32) -> f32 {
+        let vec = self.vector(node);
+        match self.metric {
+            // Для косинуса векторы хранятся нормированными
+            Metric::Cosine | Metric::Dot => -vec.iter().zip(query).map(|(a, b)| a * b).sum::<f32>(),
+            Metric::L2 => vec.iter().zip(query).map(|(a, b)| (a - b) * (a - b)).sum(),
+        }
+    }
+
+    fn prepare(&self, vec: &[f32]) -> Vec<f32> {
+        match self.metric {
+            Metric::Cosine => normalize(vec),
+            Metric::Dot | Metric::L2 => vec.to_vec(),
+        }
+    }
+
+    fn random_level(&mut self) -> usize {
+        // xorshift64: уровни не обязаны быть криптостойкими
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let r = ((self.rng >> 11) as f64 / (1u64 << 53) as f64).max(f64::MIN_POSITIVE);
+        let ml = 1.0 / (self.params.m.max(2) as f64).ln();
+        (-r.ln() * ml).floor() as usize
+    }
+
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entry: &[u32],
+        ef: usize,
+        level: usize,
+    ) -> Vec<Candidate> {
+        let mut visited: HashSet<u32> = entry.iter().copied().collect();
+        let mut candidates = BinaryHeap::new();
+        let mut results = BinaryHeap::new();
+
+        for &node in entry {
+            let c = Candidate {
+                distance: self.distance(query, node),
+                node,
+            };
+            candidates.push(Reverse(c));
+            results.push(c);
+        }
+
+        while let Some(Reverse(current)) = candidates.pop() {
+            let worst = results
+                .peek()
+                .map(|c: &Candidate| c.distance)
+                .unwrap_or(f32::MAX);
+            if current.distance > worst && results.len() >= ef {
+                break;
+            }
+
+            for &neighbor in &self.nodes[current.node as usize].neighbors[level] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let distance = self.distance(query, neighbor);
+                let worst = results
+                    .peek()
+                    .map(|c: &Candidate| c.distance)
+                    .unwrap_or(f32::MAX);
+                if results.len() < ef || distance < worst {
+                    let c = Candidate {
+                        distance,
+                        node: neighbor,
+                    };
+                    candidates.push(Reverse(c));
+                    results.push(c);
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        results.into_sorted_vec()
+    }
+
+    fn greedy_descend(&self, query: &[f32], mut entry: u32, from: usize, to: usize) -> u32 {
+        for level in (to..=from).rev() {
+            if let Some(c) = self.search_layer(query, &[entry], 1, level).first() {
+                entry = c.node;
+            }
+        }
+        entry
+    }
+
+    fn insert(
+        &mut self,
+        chunk_id: Uuid,
+        embending_id: Uuid,
+        metadata: Metadata,
+        vec: &[f32],
+    ) -> Result<(), Error> {
+        if self.dim == 0 {
+            self.dim = vec.len();
+        }
+        if vec.len() != self.dim || vec.is_empty() {
+            return Err(Error);
+        }
+
+        // Повторное сохранение чанка заменяет старый узел
+        if let Some(old) = self.by_chunk.remove(&chunk_id) {
+            self.nodes[old as usize].deleted = true;
+        }
+
+        let node = self.nodes.len() as u32;
+        let level = self.random_level();
+        let vec = self.prepare(vec);
+        self.vectors.push(&vec);
+        self.nodes.push(Node {
+            chunk_id,
+            embending_id,
+            deleted: false,
+            metadata,
+            neighbors: vec![Vec::new(); level + 1],
+        });
+        self.by_chunk.insert(chunk_id, node);
+
+        let Some(entry) = self.entry_point else {
+            self.entry_point = Some(node);
+            self.max_level = level;
+            return Ok(());
+        };
+
+        let mut entry = vec![if level < self.max_level {
+            self.greedy_descend(&vec, entry, self.max_level, level + 1)
+        } else {
+            entry
+        }];
+
+        for l in (0..=level.min(self.max_level)).rev() {
+            let found = self.search_layer(&vec, &entry, self.params.ef_construction, l);
+            let max_links = if l == 0 {
+                self.params.m * 2
+            } else {
+                self.params.m
+            };
+
+            let selected = self.select_neighbors(&found, self.params.m);
+            for &neighbor in &selected {
+                self.nodes[neighbor as usize].neighbors[l].push(node);
+                if self.nodes[neighbor as usize].neighbors[l].len() > max_links {
+                    self.shrink(neighbor, l, max_links);
+                }
+            }
+            self.nodes[node as usize].neighbors[l] = selected;
+            entry = found.iter().map(|c| c.node).collect();
+        }
+
+        if level > self.max_level {
+            self.max_level = level;
+            self.entry_point = Some(node);
+        }
+        Ok(())
+    }
+
+    // Эвристика отбора соседей из статьи HNSW: кандидат берётся, только если он
+    // ближе к базовому узлу, чем к уже отобранным. Так связи покрывают разные
+    // направления, а не сбиваются в один кластер. Недобор заполняется отброшенными.
+    fn select_neighbors(&self, sorted: &[Candidate], max_links: usize) -> Vec<u32> {
+        let mut selected: Vec<u32> = Vec::with_capacity(max_links);
+        let mut pruned = Vec::new();
+
+        for c in sorted {
+            if selected.len() >= max_links {
+                break;
+            }
+            let candidate = self.vector(c.node);
+            let diverse = selected
+                .iter()
+                .all(|&s| self.distance(candidate, s) > c.distance);
+            if diverse {
+                selected.push(c.node);
+            } else {
+                pruned.push(c.node);
+            }
+        }
+
+        let missing = max_links.saturating_sub(selected.len());
+        selected.extend(pruned.into_iter().take(missing));
+        selected
+    }
+
+    // Оставляет у узла не больше max_links соседей
+    fn shrink(&mut self, node: u32, level: usize, max_links: usize) {
+        let base = self.vector(node).to_vec();
+        let mut links: Vec<Candidate> = self.nodes[node as usize].neighbors[level]
+            .iter()
+            .map(|&n| Candidate {
+                distance: self.distance(&base, n),
+                node: n,
+            })
+            .collect();
+        links.sort();
+        self.nodes[node as usize].neighbors[level] = self.select_neighbors(&links, max_links);
+    }
+
+    // Обратное преобразование расстояния в близость из Metric::similarity
+    fn score(&self, distance: f32) -> f32 {
+        match self.metric {
+            Metric::Cosine | Metric::Dot => -distance,
+            Metric::L2 => -distance.sqrt(),
+        }
+    }
+
+    fn search(&self, query: &[f32], top_k: usize, filter: Option<&Filter>) -> Vec<ScoredChunk> {
+        let Some(entry) = self.entry_point else {
+            return Vec::new();
+        };
+        if query.len() != self.dim {
+            return Vec::new();
+        }
+
+        let query = self.prepare(query);
+        let entry = self.greedy_descend(&query, entry, self.max_level, 1);
+        let matches =
+            |node: &Node| !node.deleted && filter.is_none_or(|f| f.matches(&node.metadata));
+
+        // Фильтр применяется к найденным кандидатам; если подходящих меньше top_k,
+        // расширяем очередь кандидатов, пока не переберём весь граф
+        let mut ef = self.params.ef_search.max(top_k);
+        loop {
+            let found: Vec<Candidate> = self
+                .search_layer(&query, &[entry], ef, 0)
+                .into_iter()
+                .filter(|c| matches(&self.nodes[c.node as usize]))
+                .take(top_k)
+                .collect();
+
+            if found.len() >= top_k || ef >= self.nodes.len() {
+                return found
+                    .into_iter()
+                    .map(|c| ScoredChunk {
+                        chunk_id: self.nodes[c.node as usize].chunk_id,
+                        score: self.score(c.distance),
+                    })
+                    .collect();
+            }
+            ef = (ef * 2).min(self.nodes.len());
+        }
+    }
+}
+
+// Приблизительный поиск ближайших соседей (HNSW) в памяти процесса.
+// Удаление помечает узел, физически он исчезает после compact().
+pub struct HnswIndex {
+    graph: RwLock<Graph>,
+}
+
+impl HnswIndex {
+    pub fn new(metric: Metric, params: HnswParams) -> Self {
+        Self {
+            graph: RwLock::new(Graph {
+                metric,
+                params,
+                model_id: String::new(),
+                dim: 0,
+                nodes: Vec::new(),
+                vectors: Vectors::Owned(Vec::new()),
+                by_chunk: HashMap::new(),
+                entry_point: None,
+                max_level: 0,
+                rng: 0x2545_f491_4f6c_dd1d,
+            }),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.graph.read().unwrap().by_chunk.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Размер очереди кандидатов при поиске: больше — точнее, но медленнее
+    pub fn set_ef_search(&self, ef_search: usize) {
+        self.graph.write().unwrap().params.ef_search = ef_search;
+    }
+
+    // Перестраивает граф без удалённых узлов
+    pub fn compact(&self) -> Result<(), Error> {
+        let mut graph = self.graph.write().unwrap();
+        let mut rebuilt = Graph {
+            metric: graph.metric,
+            params: graph.params,
+            model_id: graph.model_id.clone(),
+            dim: 0,
+            nodes: Vec::new(),
+            vectors: Vectors::Owned(Vec::new()),
+            by_chunk: HashMap::new(),
+            entry_point: None,
+            max_level: 0,
+            rng: graph.rng,
+        };
+        for (i, node) in graph.nodes.iter().enumerate() {
+            if !node.deleted {
+                rebuilt.insert(
+                    node.chunk_id,
+                    node.embending_id,
+                    node.metadata.clone(),
+                    graph.vector(i as u32),
+                )?;
+            }
+        }
+        *graph = rebuilt;
+        Ok(())
+    }
+
+    pub fn persist(&self, path: &Path) -> Result<(), Error> {
+        let graph = self.graph.read().unwrap();
+        let mut out = Vec::new();
+
+        out.extend_from_slice(MAGIC);
+        for value in [
+            FORMAT_VERSION,
+            graph.metric as u32,
+            graph.params.m as u32,
+            graph.params.ef_construction as u32,
+            graph.params.ef_search as u32,
+            graph.dim as u32,
+            graph.nodes.len() as u32,
+            graph.max_level as u32,
+            graph.entry_point.unwrap_or(NO_ENTRY_POINT),
+            graph.model_id.len() as u32,
+        ] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out.extend_from_slice(&graph.rng.to_le_bytes());
+        out.extend_from_slice(graph.model_id.as_bytes());
+
+        for node in &graph.nodes {
+            out.extend_from_slice(node.chunk_id.as_bytes());
+            out.extend_from_slice(node.embending_id.as_bytes());
+            out.push(node.deleted as u8);
+            let metadata = serde_json::to_vec(&node.metadata).map_err(|_| Error)?;
+            out.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+            out.extend_from_slice(&metadata);
+            out.push(node.neighbors.len() as u8);
+            for links in &node.neighbors {
+                out.extend_from_slice(&(links.len() as u32).to_le_bytes());
+                for link in links {
+                    out.extend_from_slice(&link.to_le_bytes());
+                }
+            }
+        }
+
+        // Векторы выравниваем на 4 байта, чтобы читать их из mmap без копирования
+        out.resize(out.len().next_multiple_of(size_of::<f32>()), 0);
+        for value in graph.vectors.as_slice() {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+
+        // Пишем во временный файл и подменяем им индекс: открытый по path индекс
+        // продолжает читать векторы из старого файла, а сбой записи не портит его
+        let tmp = path.with_extension("tmp");
+        let mut file = BufWriter::new(File::create(&tmp).map_err(|_| Error)?);
+        file.write_all(&out).map_err(|_| Error)?;
+        let file = file.into_inner().map_err(|_| Error)?;
+        file.sync_all().map_err(|_| Error)?;
+        std::fs::rename(&tmp, path).map_err(|_| Error)
+    }
+
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path).map_err(|_| Error)?;
+        // SAFETY: файл индекса не должен изменяться, пока он отображён в память
+        let map = unsafe { Mmap::map(&file) }.map_err(|_| Error)?;
+        let mut reader = Reader { data: &map, pos: 0 };
+
+        if reader.bytes(MAGIC.len())? != MAGIC || reader.u32()? != FORMAT_VERSION {
+            return Err(Error);
+        }
+        let metric = match reader.u32()? {
+            0 => Metric::Cosine,
+            1 => Metric::Dot,
+            2 => Metric::L2,
+            _ => return Err(Error),
+        };
+        let params = HnswParams {
+            m: reader.u32()? as usize,
+            ef_construction: reader.u32()? as usize,
+            ef_search: reader.u32()? as usize,
+        };
+        let dim = reader.u32()? as usize;
+        let count = reader.u32()? as usize;
+        let max_level = reader.u32()? as usize;
+        let entry_point = match reader.u32()? {
+            NO_ENTRY_POINT => None,
+            node => Some(node),
+        };
+        let model_id_len = reader.u32()? as usize;
+        let rng = u64::from_le_bytes(reader.bytes(8)?.try_into().unwrap());
+        let model_id =
+            String::from_utf8(reader.bytes(model_id_len)?.to_vec()).map_err(|_| Error)?;
+
+        let mut nodes = Vec::with_capacity(count);
+        let mut by_chunk = HashMap::with_capacity(count);
+        for i in 0..count {
+            let chunk_id = Uuid::from_slice(reader.bytes(16)?).map_err(|_| Error)?;
+            let embending_id = Uuid::from_slice(reader.bytes(16)?).map_err(|_| Error)?;
+            let deleted = reader.bytes(1)?[0] != 0;
+            let metadata_len = reader.u32()? as usize;
+            let metadata =
+                serde_json::from_slice(reader.bytes(metadata_len)?).map_err(|_| Error)?;
+            let levels = reader.bytes(1)?[0] as usize;
+            let mut neighbors = Vec::with_capacity(levels);
+            for _ in 0..levels {
+                let len = reader.u32()? as usize;
+                neighbors.push((0..len).map(|_| reader.u32()).collect::<Result<_, _>>()?);
+            }
+            if !deleted {
+                by_chunk.insert(chunk_id, i as u32);
+            }
+            nodes.push(Node {
+                chunk_id,
+                embending_id,
+                deleted,
+                metadata,
+                neighbors,
+            });
+        }
+
+        let offset = reader.pos.next_multiple_of(size_of::<f32>());
+        let len = count * dim;
+        if map.len() < offset + len * size_of::<f32>() {
+            return Err(Error);
+        }
+
+        let vectors = if cfg!(target_endian = "little") {
+            Vectors::Mapped { map, offset, len }
+        } else {
+            // На big-endian векторы декодируются в память
+            let bytes = &map[offset..offset + len * size_of::<f32>()];
+            Vectors::Owned(
+                bytes
+                    .chunks_exact(size_of::<f32>())
+                    .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                    .collect(),
+            )
+        };
+
+        Ok(Self {
+            graph: RwLock::new(Graph {
+                metric,
+                params,
+                model_id,
+                dim,
+                nodes,
+                vectors,
+                by_chunk,
+                entry_point,
+                max_level,
+                rng,
+            }),
+        })
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or(Error)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+}
+
+#[async_trait::async_trait]
+impl ChunkEmbendingRepo for HnswIndex {
+    async fn save(&self, embedding: &ChunkEmbending) -> Result<(), Error> {
+        let mut graph = self.graph.write().unwrap();
+        if graph.model_id.is_empty() {
+            graph.model_id = embedding.model_id.clone();
+        }
+        graph.insert(
+            embedding.chunk_id,
+            embedding.id,
+            embedding.metadata.clone(),
+            &embedding.vec,
+        )
+    }
+
+    async fn delete(&self, chunk_id: Uuid) -> Result<(), Error> {
+        let mut graph = self.graph.write().unwrap();
+        if let Some(node) = graph.by_chunk.remove(&chunk_id) {
+            graph.nodes[node as usize].deleted = true;
+        }
+        Ok(())
+    }
+
+    async fn read(&self, chunk_id: Uuid) -> Result<ChunkEmbending, Error> {
+        let graph = self.graph.read().unwrap();
+        let node = *graph.by_chunk.get(&chunk_id).ok_or(Error)?;
+        Ok(ChunkEmbending {
+            id: graph.nodes[node as usize].embending_id,
+            chunk_id,
+            model_id: graph.model_id.clone(),
+            vec: graph.vector(node).to_vec(),
+            metadata: graph.nodes[node as usize].metadata.clone(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl VectorSearcher for HnswIndex {
+    fn metric(&self) -> Metric {
+        self.graph.read().unwrap().metric
+    }
+
+    async fn search_similar<'a>(
+        &self,
+        vector: &[f32],
+        top_k: usize,
+        min_score: Option<f32>,
+        filter: Option<&'a Filter>,
+    ) -> Result<Vec<ScoredChunk>, Error> {
+        let graph = self.graph.read().unwrap();
+        let mut found = graph.search(vector, top_k, filter);
+        if let Some(min_score) = min_score {
+            found.retain(|c| c.score >= min_score);
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::domain::filter::MetadataValue;
+    use crate::testing::{ids, random_vectors};
+
+    fn exact_search(metric: Metric, vectors: &[Vec<f32>], query: &[f32], k: usize) -> Vec<u128> {
+        let mut scored: Vec<(u128, f32)> = vectors
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i as u128, metric.similarity(query, v)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.into_iter().take(k).map(|(id, _)| id).collect()
+    }
+
+    async fn build(metric: Metric, vectors: &[Vec<f32>]) -> HnswIndex {
+        let index = HnswIndex::new(metric, HnswParams::default());
+        for (i, vec) in vectors.iter().enumerate() {
+            let embending = ChunkEmbending {
+                id: Uuid::new_v4(),
+                chunk_id: Uuid::from_u128(i as u128),
+                model_id: "test".into(),
+                vec: vec.clone(),
+                metadata: Metadata::from([("bucket".to_string(), ((i % 10) as i64).into())]),
+            };
+            index.save(&embending).await.unwrap();
+        }
+        index
+    }
+
+    async fn recall_and_latency(
+        metric: Metric,
+        index: &HnswIndex,
+        vectors: &[Vec<f32>],
+        queries: &[Vec<f32>],
+        k: usize,
+    ) -> (f32, Duration, Duration) {
+        let mut hits = 0;
+        let mut exact_time = Duration::ZERO;
+        let mut hnsw_time = Duration::ZERO;
+        for query in queries {
+            let started = Instant::now();
+            let expected = exact_search(metric, vectors, query, k);
+            exact_time += started.elapsed();
+
+            let started = Instant::now();
+            let found = ids(&index.search_similar(query, k, None, None).await.unwrap());
+            hnsw_time += started.elapsed();
+
+            hits += found.iter().filter(|id| expected.contains(id)).count();
+        }
+        let n = queries.len() as u32;
+        (
+            hits as f32 / (queries.len() * k) as f32,
+            exact_time / n,
+            hnsw_time / n,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_recall_against_exact_search() {
+        let vectors = random_vectors(1000, 32, 42);
+        let queries = random_vectors(50, 32, 7);
+
+        for metric in [Metric::Cosine, Metric::Dot, Metric::L2] {
+            let index = build(metric, &vectors).await;
+            let (recall, _, _) = recall_and_latency(metric, &index, &vectors, &queries, 10).await;
+            assert!(recall >= 0.9, "{metric:?} recall@10 = {recall}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scores_follow_metric_and_min_score() {
+        let vectors = random_vectors(100, 8, 11);
+        let query = &vectors[3];
+
+        for metric in [Metric::Cosine, Metric::Dot, Metric::L2] {
+            let index = build(metric, &vectors).await;
+            let found = index.search_similar(query, 5, None, None).await.unwrap();
+
+            for (c, next) in found.iter().zip(found.iter().skip(1)) {
+                assert!(c.score >= next.score);
+            }
+            for c in &found {
+                let vec = &vectors[c.chunk_id.as_u128() as usize];
+                assert!((c.score - metric.similarity(query, vec)).abs() < 1e-4);
+            }
+
+            let min_score = found[2].score;
+            let filtered = index
+                .search_similar(query, 5, Some(min_score), None)
+                .await
+                .unwrap();
+            assert_eq!(filtered, found[..3].to_vec());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_filtered_search_matches_exact_search() {
+        let vectors = random_vectors(1000, 16, 21);
+        let index = build(Metric::Cosine, &vectors).await;
+        // Под фильтр попадает только каждый десятый вектор
+        let filter = Filter::eq("bucket", 7i64);
+        let allowed: Vec<Vec<f32>> = vectors
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                if i % 10 == 7 {
+                    v.clone()
+                } else {
+                    vec![0.0; 16]
+                }
+            })
+            .collect();
+
+        for query in random_vectors(10, 16, 4) {
+            let found = index
+                .search_similar(&query, 10, None, Some(&filter))
+                .await
+                .unwrap();
+            let expected = exact_search(Metric::Cosine, &allowed, &query, 10);
+
+            assert_eq!(found.len(), 10);
+            assert!(found.iter().all(|c| c.chunk_id.as_u128() % 10 == 7));
+            let hits = found
+                .iter()
+                .filter(|c| expected.contains(&c.chunk_id.as_u128()))
+                .count();
+            assert!(hits >= 9, "filtered recall = {hits}/10");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deleted_chunks_are_not_returned() {
+        let vectors = random_vectors(200, 16, 3);
+        let index = build(Metric::Cosine, &vectors).await;
+        let target = Uuid::from_u128(10);
+
+        assert_eq!(
+            ids(&index
+                .search_similar(&vectors[10], 1, None, None)
+                .await
+                .unwrap()),
+            vec![10]
+        );
+
+        index.delete(target).await.unwrap();
+        assert!(
+            !ids(&index
+                .search_similar(&vectors[10], 5, None, None)
+                .await
+                .unwrap())
+            .contains(&10)
+        );
+        assert!(index.read(target).await.is_err());
+
+        index.compact().unwrap();
+        assert_eq!(index.len(), 199);
+        assert!(
+            !ids(&index
+                .search_similar(&vectors[10], 5, None, None)
+                .await
+                .unwrap())
+            .contains(&10)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_returns_vector_as_indexed() {
+        let vectors = random_vectors(20, 16, 4);
+        let cosine = build(Metric::Cosine, &vectors).await;
+        let l2 = build(Metric::L2, &vectors).await;
+
+        // Для косинусной метрики индекс хранит нормализованные векторы
+        let stored = cosine.read(Uuid::from_u128(1)).await.unwrap().vec;
+        assert_ne!(stored, vectors[1]);
+        let norm = stored.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5);
+        assert!(Metric::Cosine.similarity(&stored, &vectors[1]) > 0.9999);
+
+        assert_eq!(l2.read(Uuid::from_u128(1)).await.unwrap().vec, vectors[1]);
+    }
+
+    #[tokio::test]
+    async fn test_persist_and_open_mapped() {
+        let vectors = random_vectors(300, 16, 5);
+        let index = build(Metric::L2, &vectors).await;
+        index.delete(Uuid::from_u128(0)).await.unwrap();
+        let path = std::env::temp_dir().join(format!("hnsw-{}.idx", Uuid::new_v4()));
+
+        index.persist(&path).unwrap();
+        let opened = HnswIndex::open(&path).unwrap();
+
+        assert_eq!(opened.len(), index.len());
+        for query in random_vectors(10, 16, 9) {
+            assert_eq!(
+                ids(&opened.search_similar(&query, 5, None, None).await.unwrap()),
+                ids(&index.search_similar(&query, 5, None, None).await.unwrap())
+            );
+        }
+        let stored = opened.read(Uuid::from_u128(1)).await.unwrap();
+        assert_eq!(stored.vec, vectors[1]);
+        assert_eq!(stored.metadata["bucket"], MetadataValue::Int(1));
+        assert_eq!(stored.model_id, "test");
+
+        // После открытия индекс остаётся изменяемым
+        let extra = ChunkEmbending {
+            id: Uuid::new_v4(),
+            chunk_id: Uuid::new_v4(),
+            model_id: "test".into(),
+            vec: vec![10.0; 16],
+            metadata: Metadata::new(),
+        };
+        opened.save(&extra).await.unwrap();
+        assert_eq!(
+            ids(&opened
+                .search_similar(&[10.0; 16], 1, None, None)
+                .await
+                .unwrap()),
+            vec![extra.chunk_id.as_u128()]
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_persist_over_mapped_file() {
+        let vectors = random_vectors(200, 16, 3);
+        let index = build(Metric::Cosine, &vectors).await;
+        let path = std::env::temp_dir().join(format!("hnsw-{}.idx", Uuid::new_v4()));
+        index.persist(&path).unwrap();
+
+        // Векторы открытого индекса отображены из того же файла, который перезаписывается
+        let opened = HnswIndex::open(&path).unwrap();
+        opened.delete(Uuid::from_u128(0)).await.unwrap();
+        opened.persist(&path).unwrap();
+
+        let queries = random_vectors(10, 16, 11);
+        let reopened = HnswIndex::open(&path).unwrap();
+        assert_eq!(reopened.len(), opened.len());
+        for query in &queries {
+            let expected = ids(&opened.search_similar(query, 5, None, None).await.unwrap());
+            assert!(!expected.contains(&0));
+            assert_eq!(
+                ids(&reopened.search_similar(query, 5, None, None).await.unwrap()),
+                expected
+            );
+        }
+        assert!(!path.with_extension("tmp").exists());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // Векторы эмбеддингов обычно сгруппированы по темам, поэтому бенчмарк
+    // строится на кластерах, а не на равномерном шуме
+    fn clustered_vectors(count: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+        let centers = random_vectors(100, dim, 1);
+        let noise = random_vectors(count, dim, seed);
+        noise
+            .into_iter()
+            .enumerate()
+            .map(|(i, n)| {
+                let center = &centers[i % centers.len()];
+                center.iter().zip(n).map(|(c, n)| c + 0.3 * n).collect()
+            })
+            .collect()
+    }
+
+    // cargo test --release bench_hnsw -- --ignored --nocapture
+    #[tokio::test]
+    #[ignore]
+    async fn bench_hnsw_against_exact_search() {
+        let vectors = clustered_vectors(20_000, 128, 42);
+        let queries = clustered_vectors(200, 128, 7);
+
+        for metric in [Metric::Cosine, Metric::Dot, Metric::L2] {
+            let index = build(metric, &vectors).await;
+            for ef_search in [16, 64, 256] {
+                index.set_ef_search(ef_search);
+                for k in [1, 10] {
+                    let (recall, exact, hnsw) =
+                        recall_and_latency(metric, &index, &vectors, &queries, k).await;
+                    println!(
+                        "{metric:?} ef={ef_search} k={k}: recall={recall:.3} exact={exact:?} hnsw={hnsw:?} speedup={:.1}x",
+                        exact.as_secs_f64() / hnsw.as_secs_f64()
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::fmt::Error;
+use std::sync::RwLock;
+
+use uuid::Uuid;
+
+use crate::adapter::quantization::{Quantization, QuantizedVector, normalize};
+use crate::domain::embedding::{
+    ChunkEmbending, ChunkEmbendingRepo, Metric, ScoredChunk, VectorSearcher,
+};
+use crate::domain::filter::{Filter, Metadata};
+
+struct Entry {
+    id: Uuid,
+    model_id: String,
+    vec: QuantizedVector,
+    metadata: Metadata,
+}
+
+// Точный (brute-force) поиск по эмбеддингам, хранящимся в памяти процесса
+pub struct InMemoryVectorIndex {
+    quantization: Quantization,
+    entries: RwLock<HashMap<Uuid, Entry>>,
+}
+
+impl InMemoryVectorIndex {
+    pub fn new(quantization: Quantization) -> Self {
+        Self {
+            quantization,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn size_in_bytes(&self) -> usize {
+        self.entries
+            .read()
+            .unwrap()
+            .values()
+            .map(|e| e.vec.size_in_bytes())
+            .sum()
+    }
+}
+
+#[async_trait::async_trait]
+impl ChunkEmbendingRepo for InMemoryVectorIndex {
+    async fn save(&self, embedding: &ChunkEmbending) -> Result<(), Error> {
+        let entry = Entry {
+            id: embedding.id,
+            model_id: embedding.model_id.clone(),
+            vec: QuantizedVector::encode(&embedding.vec, self.quantization),
+            metadata: embedding.metadata.clone(),
+        };
+        self.entries
+            .write()
+            .unwrap()
+            .insert(embedding.chunk_id, entry);
+        Ok(())
+    }
+
+    async fn delete(&self, chunk_id: Uuid) -> Result<(), Error> {
+        self.entries.write().unwrap().remove(&chunk_id);
+        Ok(())
+    }
+
+    async fn read(&self, chunk_id: Uuid) -> Result<ChunkEmbending, Error> {
+        let entries = self.entries.read().unwrap();
+        let entry = entries.get(&chunk_id).ok_or(Error)?;
+        Ok(ChunkEmbending {
+            id: entry.id,
+            chunk_id,
+            model_id: entry.model_id.clone(),
+            vec: entry.vec.decode(),
+            metadata: entry.metadata.clone(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl VectorSearcher for InMemoryVectorIndex {
+    fn metric(&self) -> Metric {
+        Metric::Cosine
+    }
+
+    async fn search_similar<'a>(
+        &self,
+        vector: &[f32],
+        top_k: usize,
+        min_score: Option<f32>,
+        filter: Option<&'a Filter>,
+    ) -> Result<Vec<ScoredChunk>, Error> {
+        let query = normalize(vector);
+        let entries = self.entries.read().unwrap();
+
+        let mut scored: Vec<ScoredChunk> = entries
+            .iter()
+            .filter(|(_, entry)| filter.is_none_or(|f| f.matches(&entry.metadata)))
+            .map(|(chunk_id, entry)| ScoredChunk {
+                chunk_id: *chunk_id,
+                score: entry.vec.score(&query),
+            })
+            .filter(|c| min_score.is_none_or(|min| c.score >= min))
+            .collect();
+        scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+        scored.truncate(top_k);
+
+        Ok(scored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{ids, random_vectors};
+
+    async fn build(quantization: Quantization, vectors: &[Vec<f32>]) -> InMemoryVectorIndex {
+        let index = InMemoryVectorIndex::new(quantization);
+        for (i, vec) in vectors.iter().enumerate() {
+            let embending = ChunkEmbending {
+                id: Uuid::new_v4(),
+                chunk_id: Uuid::from_u128(i as u128),
+                model_id: "test".into(),
+                vec: vec.clone(),
+                metadata: Metadata::from([("parity".to_string(), ((i % 2) as i64).into())]),
+            };
+            index.save(&embending).await.unwrap();
+        }
+        index
+    }
+
+    async fn recall_at_k(quantization: Quantization, k: usize) -> f32 {
+        let vectors = random_vectors(1000, 64, 42);
+        let queries = random_vectors(50, 64, 7);
+        let baseline = build(Quantization::None, &vectors).await;
+        let quantized = build(quantization, &vectors).await;
+
+        let mut hits = 0;
+        for query in &queries {
+            let expected = ids(&baseline.search_similar(query, k, None, None).await.unwrap());
+            let found = ids(&quantized
+                .search_similar(query, k, None, None)
+                .await
+                .unwrap());
+            hits += found.iter().filter(|id| expected.contains(id)).count();
+        }
+        hits as f32 / (queries.len() * k) as f32
+    }
+
+    #[tokio::test]
+    async fn test_search_returns_nearest_first() {
+        let index = build(
+            Quantization::None,
+            &[vec![1.0, 0.0], vec![0.0, 1.0], vec![0.7, 0.7]],
+        )
+        .await;
+
+        let found = ids(&index
+            .search_similar(&[1.0, 0.1], 2, None, None)
+            .await
+            .unwrap());
+
+        assert_eq!(found, vec![0, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_search_applies_filter() {
+        let index = build(
+            Quantization::None,
+            &[
+                vec![1.0, 0.0],
+                vec![0.9, 0.1],
+                vec![0.8, 0.2],
+                vec![0.0, 1.0],
+            ],
+        )
+        .await;
+        let odd = Filter::eq("parity", 1i64);
+
+        let found = index
+            .search_similar(&[1.0, 0.0], 2, None, Some(&odd))
+            .await
+            .unwrap();
+
+        assert_eq!(ids(&found), vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_int8_recall_against_full_precision() {
+        let recall = recall_at_k(Quantization::Int8, 10).await;
+        assert!(recall >= 0.95, "int8 recall@10 = {recall}");
+    }
+
+    #[tokio::test]
+    async fn test_binary_recall_against_full_precision() {
+        let recall = recall_at_k(Quantization::Binary, 10).await;
+        assert!(recall >= 0.4, "binary recall@10 = {recall}");
+    }
+
+    #[tokio::test]
+    async fn test_quantized_index_is_smaller() {
+        let vectors = random_vectors(100, 64, 1);
+        let full = build(Quantization::None, &vectors).await.size_in_bytes();
+        let int8 = build(Quantization::Int8, &vectors).await.size_in_bytes();
+        let binary = build(Quantization::Binary, &vectors).await.size_in_bytes();
+
+        assert!(int8 * 3 < full);
+        assert!(binary * 16 < full);
+    }
+
+    #[tokio::test]
+    async fn test_read_returns_normalized_stored_vector() {
+        let original = vec![3.0, 4.0];
+        for quantization in [Quantization::None, Quantization::Int8] {
+            let index = build(quantization, std::slice::from_ref(&original)).await;
+
+            let stored = index.read(Uuid::from_u128(0)).await.unwrap().vec;
+
+            // Вектор хранится нормализованным и квантованным, поэтому отличается от сохранённого
+            assert_ne!(stored, original);
+            assert!((stored[0] - 0.6).abs() < 0.01 && (stored[1] - 0.8).abs() < 0.01);
+            assert!(Metric::Cosine.similarity(&stored, &original) > 0.999);
+        }
+    }
+}
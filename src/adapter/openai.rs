@@ -0,0 +1,562 @@
+use std::collections::VecDeque;
+use std::fmt::Error;
+use std::time::Duration;
+
+use futures::StreamExt;
+use futures::stream::{self, BoxStream};
+use serde_json::{Value, json};
+
+use crate::domain::prompt::{HistoryTurn, Prompt};
+use crate::domain::unswer::{Generation, LLM, TokenStream, TokenUsage};
+
+const SYSTEM_PROMPT: &str = "Отвечай на вопрос только по приведённым фрагментам. \
+    Фрагменты пронумерованы; после каждого утверждения ставь ссылку на фрагменты, \
+    на которых оно основано, в виде [2] или [1, 3]. \
+    Если во фрагментах нет ответа, так и скажи.";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatParams {
+    pub temperature: f32,
+    pub max_tokens: Option<u32>,
+    // Ограничение на весь запрос; для потоковых ответов — только на подключение
+    pub timeout: Duration,
+    // Потоковый ответ обрывается ошибкой, если заголовки или очередной кусок
+    // не пришли за это время
+    pub read_timeout: Duration,
+    // Повторы при сетевых ошибках, 429 и 5xx
+    pub retries: usize,
+    // Пауза перед первым повтором, дальше удваивается
+    pub backoff: Duration,
+}
+
+impl Default for ChatParams {
+    fn default() -> Self {
+        Self {
+            temperature: 0.2,
+            max_tokens: None,
+            timeout: Duration::from_secs(60),
+            read_timeout: Duration::from_secs(30),
+            retries: 2,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+// Модель за протоколом /v1/chat/completions: OpenAI, vLLM, llama.cpp server, Ollama
+pub struct OpenAiLlm {
+    client: reqwest::Client,
+    url: String,
+    model: String,
+    api_key: Option<String>,
+    params: ChatParams,
+}
+
+impl OpenAiLlm {
+    pub fn new(url: &str, model: &str, params: ChatParams) -> Result<Self, Error> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(params.timeout)
+            .build()
+            .map_err(|_| Error)?;
+        Ok(Self {
+            client,
+            url: url.trim_end_matches('/').to_string(),
+            model: model.to_string(),
+            api_key: None,
+            params,
+        })
+    }
+
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    fn body(&self, messages: Value, stream: bool) -> Value {
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages,
+            "temperature": self.params.temperature,
+            "stream": stream,
+        });
+        if let Some(max_tokens) = self.params.max_tokens {
+            body["max_tokens"] = max_tokens.into();
+        }
+        body
+    }
+
+    async fn send(&self, body: &Value, stream: bool) -> Result<reqwest::Response, Error> {
+        let mut backoff = self.params.backoff;
+        let mut attempt = 0;
+        loop {
+            let mut request = self
+                .client
+                .post(format!("{}/v1/chat/completions", self.url))
+                .json(body);
+            if !stream {
+                request = request.timeout(self.params.timeout);
+            }
+            if let Some(api_key) = &self.api_key {
+                request = request.bearer_auth(api_key);
+            }
+
+            let response = match stream {
+                // Заголовков потокового ответа ждём не дольше, чем очередного куска
+                true => tokio::time::timeout(self.params.read_timeout, request.send())
+                    .await
+                    .ok(),
+                false => Some(request.send().await),
+            };
+            let retryable = match response {
+                Some(Ok(response)) if response.status().is_success() => return Ok(response),
+                Some(Ok(response)) => {
+                    let status = response.status();
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                }
+                Some(Err(_)) | None => true,
+            };
+            if !retryable || attempt >= self.params.retries {
+                return Err(Error);
+            }
+            attempt += 1;
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+
+    async fn chat(&self, messages: Value) -> Result<Generation, Error> {
+        let response: Value = self
+            .send(&self.body(messages, false), false)
+            .await?
+            .json()
+            .await
+            .map_err(|_| Error)?;
+        let text = response["choices"][0]["message"]["content"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or(Error)?;
+        Ok(Generation {
+            text,
+            usage: parse_usage(&response["usage"]),
+        })
+    }
+}
+
+// Предыдущие ходы разговора идут отдельными сообщениями, фрагменты — только в последнем
+fn unswer_messages(question: &str, context: &[String], history: &[HistoryTurn]) -> Value {
+    let mut user = String::from("Фрагменты:\n");
+    for (i, passage) in context.iter().enumerate() {
+        user.push_str(&format!("\n[{}] {}\n", i + 1, passage));
+    }
+    user.push_str(&format!("\nВопрос: {question}"));
+
+    let mut messages = vec![json!({ "role": "system", "content": SYSTEM_PROMPT })];
+    for turn in history {
+        messages.push(json!({ "role": "user", "content": turn.question }));
+        messages.push(json!({ "role": "assistant", "content": turn.unswer }));
+    }
+    messages.push(json!({ "role": "user", "content": user }));
+    Value::Array(messages)
+}
+
+// Серверы без подсчёта токенов поле usage не присылают
+fn parse_usage(usage: &Value) -> Option<TokenUsage> {
+    Some(TokenUsage {
+        prompt_tokens: usage["prompt_tokens"].as_u64()?.try_into().ok()?,
+        completion_tokens: usage["completion_tokens"].as_u64()?.try_into().ok()?,
+    })
+}
+
+fn prompt_messages(system: &str, user: &str) -> Value {
+    json!([
+        { "role": "system", "content": system },
+        { "role": "user", "content": user },
+    ])
+}
+
+enum SseEvent {
+    // Очередной кусок текста; finished — модель сообщила finish_reason
+    Delta {
+        text: Result<String, Error>,
+        finished: bool,
+    },
+    // Строка "data: [DONE]", конец потока
+    Done,
+}
+
+// Строка "data: {...}" потока; None — строка без данных
+fn parse_event(line: &str) -> Option<SseEvent> {
+    let data = line.strip_prefix("data:")?.trim();
+    if data == "[DONE]" {
+        return Some(SseEvent::Done);
+    }
+    let Ok(chunk) = serde_json::from_str::<Value>(data) else {
+        return Some(SseEvent::Delta {
+            text: Err(Error),
+            finished: false,
+        });
+    };
+    let choice = &chunk["choices"][0];
+    Some(SseEvent::Delta {
+        text: Ok(choice["delta"]["content"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string()),
+        finished: choice["finish_reason"].is_string(),
+    })
+}
+
+struct SseState {
+    bytes: BoxStream<'static, reqwest::Result<Vec<u8>>>,
+    buffer: Vec<u8>,
+    pending: VecDeque<Result<String, Error>>,
+    finished: bool,
+    // Модель закончила ответ: прислала [DONE] или finish_reason
+    completed: bool,
+    read_timeout: Duration,
+}
+
+impl SseState {
+    fn push_line(&mut self, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+        match parse_event(line.trim_end()) {
+            Some(SseEvent::Delta { text, finished }) => {
+                self.completed |= finished;
+                match text {
+                    Ok(text) if text.is_empty() => {}
+                    text => self.pending.push_back(text),
+                }
+            }
+            Some(SseEvent::Done) => {
+                self.completed = true;
+                self.finished = true;
+            }
+            None => {}
+        }
+    }
+}
+
+// Разбирает поток server-sent events в куски текста, пустые куски пропускаются
+fn token_stream(response: reqwest::Response, read_timeout: Duration) -> TokenStream {
+    let state = SseState {
+        bytes: response
+            .bytes_stream()
+            .map(|bytes| bytes.map(|b| b.to_vec()))
+            .boxed(),
+        buffer: Vec::new(),
+        pending: VecDeque::new(),
+        finished: false,
+        completed: false,
+        read_timeout,
+    };
+    Box::pin(stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(delta) = state.pending.pop_front() {
+                return Some((delta, state));
+            }
+            if state.finished {
+                return None;
+            }
+            let next = tokio::time::timeout(state.read_timeout, state.bytes.next()).await;
+            let Ok(next) = next else {
+                // Модель перестала присылать данные, не закрыв соединение
+                state.finished = true;
+                state.pending.push_back(Err(Error));
+                continue;
+            };
+            match next {
+                Some(Ok(bytes)) => {
+                    state.buffer.extend_from_slice(&bytes);
+                    while let Some(end) = state.buffer.iter().position(|&b| b == b'\n') {
+                        if state.finished {
+                            break;
+                        }
+                        let line: Vec<u8> = state.buffer.drain(..=end).collect();
+                        state.push_line(&line);
+                    }
+                }
+                Some(Err(_)) => {
+                    state.finished = true;
+                    state.pending.push_back(Err(Error));
+                }
+                None => {
+                    state.finished = true;
+                    // Последняя строка могла прийти без перевода строки
+                    let line = std::mem::take(&mut state.buffer);
+                    state.push_line(&line);
+                    // Соединение закрыто до конца ответа: текст оборван
+                    if !state.completed {
+                        state.pending.push_back(Err(Error));
+                    }
+                }
+            }
+        }
+    }))
+}
+
+#[async_trait::async_trait]
+impl LLM for OpenAiLlm {
+    fn model_id(&self) -> String {
+        self.model.clone()
+    }
+
+    async fn formulate_unswer(
+        &self,
+        question: String,
+        context: Vec<String>,
+        history: Vec<HistoryTurn>,
+    ) -> Result<Generation, Error> {
+        self.chat(unswer_messages(&question, &context, &history))
+            .await
+    }
+
+    async fn stream_unswer(
+        &self,
+        question: String,
+        context: Vec<String>,
+        history: Vec<HistoryTurn>,
+    ) -> Result<TokenStream, Error> {
+        let body = self.body(unswer_messages(&question, &context, &history), true);
+        let response = self.send(&body, true).await?;
+        Ok(token_stream(response, self.params.read_timeout))
+    }
+
+    async fn complete(&self, prompt: String) -> Result<String, Error> {
+        Ok(self
+            .chat(json!([{ "role": "user", "content": prompt }]))
+            .await?
+            .text)
+    }
+
+    async fn generate(&self, prompt: Prompt) -> Result<Generation, Error> {
+        self.chat(prompt_messages(&prompt.system, &prompt.user))
+            .await
+    }
+
+    async fn stream_generate(&self, prompt: Prompt) -> Result<TokenStream, Error> {
+        let body = self.body(prompt_messages(&prompt.system, &prompt.user), true);
+        let response = self.send(&body, true).await?;
+        Ok(token_stream(response, self.params.read_timeout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::serve;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn params() -> ChatParams {
+        ChatParams {
+            max_tokens: Some(256),
+            backoff: Duration::from_millis(1),
+            ..ChatParams::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unswer_is_read_from_choices() {
+        let (url, server) = serve(vec![(
+            200,
+            r#"{"choices":[{"message":{"role":"assistant","content":"Rust — язык [1]."}}]}"#,
+        )])
+        .await;
+        let llm = OpenAiLlm::new(&url, "qwen2.5", params()).unwrap();
+
+        let unswer = llm
+            .formulate_unswer(
+                "А он быстрый?".into(),
+                vec!["Rust — язык.".into()],
+                vec![HistoryTurn {
+                    question: "Что такое Rust?".into(),
+                    unswer: "Язык программирования.".into(),
+                }],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(unswer.text, "Rust — язык [1].");
+        let request = &server.await.unwrap()[0].1;
+        assert_eq!(request["model"], "qwen2.5");
+        assert_eq!(request["max_tokens"], 256);
+        assert_eq!(request["stream"], false);
+        let messages = request["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[2]["role"], "assistant");
+        let user = messages[3]["content"].as_str().unwrap();
+        assert!(user.contains("[1] Rust — язык.") && user.ends_with("Вопрос: А он быстрый?"));
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_are_retried() {
+        let (url, server) = serve(vec![
+            (503, "{}"),
+            (429, "{}"),
+            (200, r#"{"choices":[{"message":{"content":"ok"}}]}"#),
+        ])
+        .await;
+        let llm = OpenAiLlm::new(&url, "model", params()).unwrap();
+
+        assert_eq!(llm.complete("ping".into()).await.unwrap(), "ok");
+        assert_eq!(server.await.unwrap().len(), 3);
+
+        let (url, _server) = serve(vec![(400, "{}")]).await;
+        let llm = OpenAiLlm::new(&url, "model", params()).unwrap();
+        assert!(llm.complete("ping".into()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_generate_reports_usage() {
+        let (url, server) = serve(vec![(
+            200,
+            r#"{"choices":[{"message":{"content":"ok"}}],"usage":{"prompt_tokens":12,"completion_tokens":3,"total_tokens":15}}"#,
+        )])
+        .await;
+        let llm = OpenAiLlm::new(&url, "model", params()).unwrap();
+        let prompt = Prompt {
+            template_id: "answer@1".into(),
+            system: "Отвечай кратко.".into(),
+            user: "Вопрос".into(),
+        };
+
+        let generation = llm.generate(prompt).await.unwrap();
+
+        assert_eq!(
+            generation.usage,
+            Some(TokenUsage {
+                prompt_tokens: 12,
+                completion_tokens: 3,
+            })
+        );
+        let request = &server.await.unwrap()[0].1;
+        assert_eq!(request["messages"][0]["content"], "Отвечай кратко.");
+    }
+
+    #[tokio::test]
+    async fn test_stream_yields_deltas() {
+        let (url, server) = serve(vec![(
+            200,
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n\
+             data: {\"choices\":[{\"delta\":{\"content\":\"Rust — \"}}]}\n\n\
+             data: {\"choices\":[{\"delta\":{\"content\":\"язык.\"}}]}\n\n\
+             data: [DONE]\n\n",
+        )])
+        .await;
+        let llm = OpenAiLlm::new(&url, "model", params()).unwrap();
+
+        let deltas: Vec<String> = llm
+            .stream_unswer("Что такое Rust?".into(), vec![], vec![])
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(deltas, vec!["Rust — ", "язык."]);
+        assert_eq!(server.await.unwrap()[0].1["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn test_stalled_stream_fails_after_read_timeout() {
+        // Сервер отдаёт первый кусок и замолкает, не закрывая соединение
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            let chunk = "data: {\"choices\":[{\"delta\":{\"content\":\"Rust\"}}]}\n\n";
+            let head = "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\
+                        transfer-encoding: chunked\r\n\r\n";
+            let response = format!("{head}{:x}\r\n{chunk}\r\n", chunk.len());
+            socket.write_all(response.as_bytes()).await.unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+        let params = ChatParams {
+            read_timeout: Duration::from_millis(100),
+            ..params()
+        };
+        let llm = OpenAiLlm::new(&url, "model", params).unwrap();
+
+        let deltas: Vec<Result<String, Error>> = llm
+            .stream_unswer("Что такое Rust?".into(), vec![], vec![])
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(deltas, vec![Ok("Rust".to_string()), Err(Error)]);
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_stream_closed_before_done_fails() {
+        let (url, _server) = serve(vec![(
+            200,
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Rust\"}}]}\n\n",
+        )])
+        .await;
+        let llm = OpenAiLlm::new(&url, "model", params()).unwrap();
+
+        let deltas: Vec<Result<String, Error>> = llm
+            .stream_unswer("Что такое Rust?".into(), vec![], vec![])
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(deltas, vec![Ok("Rust".to_string()), Err(Error)]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_reads_last_line_without_newline() {
+        // Сервер закрывает соединение без [DONE], но с finish_reason в последней строке
+        let (url, _server) = serve(vec![(
+            200,
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Rust — \"}}]}\n\n\
+             data: {\"choices\":[{\"delta\":{\"content\":\"язык.\"},\"finish_reason\":\"stop\"}]}",
+        )])
+        .await;
+        let llm = OpenAiLlm::new(&url, "model", params()).unwrap();
+
+        let deltas: Vec<Result<String, Error>> = llm
+            .stream_unswer("Что такое Rust?".into(), vec![], vec![])
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(
+            deltas,
+            vec![Ok("Rust — ".to_string()), Ok("язык.".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_without_headers_fails_after_read_timeout() {
+        // Сервер принимает запрос и молчит
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+        let params = ChatParams {
+            read_timeout: Duration::from_millis(100),
+            retries: 0,
+            ..params()
+        };
+        let llm = OpenAiLlm::new(&url, "model", params).unwrap();
+
+        let started = std::time::Instant::now();
+        let stream = llm
+            .stream_unswer("Что такое Rust?".into(), vec![], vec![])
+            .await;
+
+        assert!(stream.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        server.abort();
+    }
+}
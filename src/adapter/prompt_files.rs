@@ -0,0 +1,89 @@
+use std::fmt::Error;
+use std::path::PathBuf;
+
+use crate::domain::prompt::{PromptTemplate, PromptTemplateRepo};
+
+const SYSTEM_SECTION: &str = "### system";
+const USER_SECTION: &str = "### user";
+
+// Шаблоны в каталоге, по файлу на версию: "{name}@{version}.prompt".
+// Файл состоит из секций, начинающихся строками "### system" и "### user".
+pub struct FilePromptTemplates {
+    dir: PathBuf,
+}
+
+impl FilePromptTemplates {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+// Имя из запроса попадает в путь к файлу, поэтому допускаются только безопасные символы
+fn parse_id(template_id: &str) -> Option<(&str, u32)> {
+    let (name, version) = template_id.split_once('@')?;
+    let safe = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !safe {
+        return None;
+    }
+    Some((name, version.parse().ok()?))
+}
+
+fn parse_sections(text: &str) -> Option<(String, String)> {
+    let mut system: Option<Vec<&str>> = None;
+    let mut user: Option<Vec<&str>> = None;
+    let mut current = None;
+
+    for line in text.lines() {
+        match line.trim_end() {
+            SYSTEM_SECTION => current = Some(system.insert(Vec::new())),
+            USER_SECTION => current = Some(user.insert(Vec::new())),
+            _ => current.as_mut()?.push(line),
+        }
+    }
+    Some((
+        system?.join("\n").trim().to_string(),
+        user?.join("\n").trim().to_string(),
+    ))
+}
+
+#[async_trait::async_trait]
+impl PromptTemplateRepo for FilePromptTemplates {
+    async fn read(&self, template_id: &str) -> Result<PromptTemplate, Error> {
+        let (name, version) = parse_id(template_id).ok_or(Error)?;
+        let path = self.dir.join(format!("{name}@{version}.prompt"));
+        let text = tokio::fs::read_to_string(path).await.map_err(|_| Error)?;
+        let (system, user) = parse_sections(&text).ok_or(Error)?;
+        PromptTemplate::new(name, version, &system, &user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_template_is_read_from_file() {
+        let dir = std::env::temp_dir().join(format!("prompts-{}", Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(
+            dir.join("answer@2.prompt"),
+            "### system\nОтвечай по-английски.\n\n### user\n{{context}}\n\nQuestion: {{question}}\n",
+        )
+        .unwrap();
+        let templates = FilePromptTemplates::new(&dir);
+
+        let template = templates.read("answer@2").await.unwrap();
+
+        assert_eq!(template.id(), "answer@2");
+        assert_eq!(template.system, "Отвечай по-английски.");
+        assert_eq!(template.user, "{{context}}\n\nQuestion: {{question}}");
+        assert!(templates.read("answer@3").await.is_err());
+        assert!(templates.read("../answer@2").await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
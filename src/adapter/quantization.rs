@@ -0,0 +1,118 @@
+// Компактные представления векторов для индекса в памяти процесса.
+// Все векторы перед кодированием нормируются, поэтому скалярное
+// произведение с нормированным запросом даёт косинусную близость.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Quantization {
+    #[default]
+    None,
+    // 1 байт на компоненту, общий масштаб на вектор
+    Int8,
+    // 1 бит на компоненту (знак)
+    Binary,
+}
+
+#[derive(Clone, Debug)]
+pub enum QuantizedVector {
+    Full(Vec<f32>),
+    Int8 { scale: f32, data: Vec<i8> },
+    Binary { dim: usize, bits: Vec<u64> },
+}
+
+pub fn normalize(vec: &[f32]) -> Vec<f32> {
+    let norm = vec.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return vec.to_vec();
+    }
+    vec.iter().map(|x| x / norm).collect()
+}
+
+impl QuantizedVector {
+    pub fn encode(vec: &[f32], quantization: Quantization) -> Self {
+        let vec = normalize(vec);
+        match quantization {
+            Quantization::None => QuantizedVector::Full(vec),
+            Quantization::Int8 => {
+                let max = vec.iter().fold(0f32, |acc, x| acc.max(x.abs()));
+                let scale = if max == 0.0 { 1.0 } else { max / 127.0 };
+                let data = vec
+                    .iter()
+                    .map(|x| (x / scale).round().clamp(-127.0, 127.0) as i8)
+                    .collect();
+                QuantizedVector::Int8 { scale, data }
+            }
+            Quantization::Binary => {
+                let mut bits = vec![0u64; vec.len().div_ceil(64)];
+                for (i, x) in vec.iter().enumerate() {
+                    if *x > 0.0 {
+                        bits[i / 64] |= 1 << (i % 64);
+                    }
+                }
+                QuantizedVector::Binary {
+                    dim: vec.len(),
+                    bits,
+                }
+            }
+        }
+    }
+
+    // Приблизительное восстановление вектора (точное для Full)
+    pub fn decode(&self) -> Vec<f32> {
+        match self {
+            QuantizedVector::Full(vec) => vec.clone(),
+            QuantizedVector::Int8 { scale, data } => {
+                data.iter().map(|x| *x as f32 * scale).collect()
+            }
+            QuantizedVector::Binary { dim, bits } => {
+                let value = 1.0 / (*dim as f32).sqrt();
+                (0..*dim)
+                    .map(|i| {
+                        if bits[i / 64] & (1 << (i % 64)) != 0 {
+                            value
+                        } else {
+                            -value
+                        }
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    // Близость к нормированному запросу; запрос не квантуется,
+    // что заметно повышает полноту по сравнению с симметричной схемой
+    pub fn score(&self, query: &[f32]) -> f32 {
+        match self {
+            QuantizedVector::Full(vec) => vec.iter().zip(query).map(|(a, b)| a * b).sum(),
+            QuantizedVector::Int8 { scale, data } => {
+                data.iter()
+                    .zip(query)
+                    .map(|(a, b)| *a as f32 * b)
+                    .sum::<f32>()
+                    * scale
+            }
+            QuantizedVector::Binary { dim, bits } => {
+                let sum: f32 = query
+                    .iter()
+                    .take(*dim)
+                    .enumerate()
+                    .map(|(i, q)| {
+                        if bits[i / 64] & (1 << (i % 64)) != 0 {
+                            *q
+                        } else {
+                            -q
+                        }
+                    })
+                    .sum();
+                sum / (*dim as f32).sqrt()
+            }
+        }
+    }
+
+    pub fn size_in_bytes(&self) -> usize {
+        match self {
+            QuantizedVector::Full(vec) => vec.len() * std::mem::size_of::<f32>(),
+            QuantizedVector::Int8 { data, .. } => data.len() + std::mem::size_of::<f32>(),
+            QuantizedVector::Binary { bits, .. } => bits.len() * std::mem::size_of::<u64>(),
+        }
+    }
+}
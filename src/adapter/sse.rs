@@ -0,0 +1,75 @@
+use std::fmt::Error;
+
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use serde_json::json;
+
+use crate::domain::unswer::{UnswerEvent, UnswerStream};
+
+// Content-Type ответа, который отдаёт поток событий
+pub const CONTENT_TYPE: &str = "text/event-stream";
+
+// Одно событие server-sent events. serde_json экранирует переводы строк,
+// поэтому data всегда занимает одну строку.
+pub fn encode(event: &Result<UnswerEvent, Error>) -> String {
+    let (name, data) = match event {
+        Ok(UnswerEvent::Delta(text)) => ("delta", json!({ "text": text })),
+        Ok(UnswerEvent::Done {
+            unswer_id,
+            text,
+            citations,
+            groundedness,
+        }) => (
+            "done",
+            json!({
+                "unswer_id": unswer_id,
+                "text": text,
+                "citations": citations,
+                "groundedness": groundedness,
+            }),
+        ),
+        Ok(UnswerEvent::NotFound { suggestions }) => {
+            ("not_found", json!({ "suggestions": suggestions }))
+        }
+        Err(_) => ("error", json!({})),
+    };
+    format!("event: {name}\ndata: {data}\n\n")
+}
+
+// Тело HTTP-ответа для потоковой выдачи ответа
+pub fn event_stream(stream: UnswerStream) -> BoxStream<'static, String> {
+    stream.map(|event| encode(&event)).boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_events_are_encoded() {
+        let unswer_id = Uuid::from_u128(7);
+        let events: UnswerStream = stream::iter(vec![
+            Ok(UnswerEvent::Delta("Rust —\nязык".into())),
+            Ok(UnswerEvent::Done {
+                unswer_id,
+                text: "Rust —\nязык".into(),
+                citations: Vec::new(),
+                groundedness: None,
+            }),
+            Err(Error),
+        ])
+        .boxed();
+
+        let body: Vec<String> = event_stream(events).collect().await;
+
+        assert_eq!(
+            body[0],
+            "event: delta\ndata: {\"text\":\"Rust —\\nязык\"}\n\n"
+        );
+        assert!(body[1].starts_with("event: done\ndata: {"));
+        assert!(body[1].contains(&format!("\"unswer_id\":\"{unswer_id}\"")));
+        assert_eq!(body[2], "event: error\ndata: {}\n\n");
+    }
+}
@@ -0,0 +1,51 @@
+use crate::domain::tokenizer::Tokenizer;
+
+// Оценка числа токенов по длине текста, когда настоящий токенизатор модели недоступен.
+// Для английского BPE-токенизаторы дают около 4 символов на токен, для русского — меньше,
+// поэтому значение по умолчанию взято с запасом.
+pub struct ApproxTokenizer {
+    chars_per_token: usize,
+}
+
+impl ApproxTokenizer {
+    // Меньше одного символа на токен не бывает, 0 приводится к 1
+    pub fn new(chars_per_token: usize) -> Self {
+        Self {
+            chars_per_token: chars_per_token.max(1),
+        }
+    }
+}
+
+impl Default for ApproxTokenizer {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+impl Tokenizer for ApproxTokenizer {
+    fn count(&self, text: &str) -> usize {
+        text.chars().count().div_ceil(self.chars_per_token)
+    }
+
+    fn truncate(&self, text: &str, max_tokens: usize) -> String {
+        text.chars()
+            .take(max_tokens * self.chars_per_token)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_and_truncates_by_chars() {
+        let tokenizer = ApproxTokenizer::default();
+
+        assert_eq!(tokenizer.count(""), 0);
+        assert_eq!(tokenizer.count("мороз"), 2);
+        assert_eq!(tokenizer.truncate("мороз и солнце", 2), "мороз ");
+        assert_eq!(tokenizer.count(&tokenizer.truncate("мороз и солнце", 2)), 2);
+        assert_eq!(ApproxTokenizer::new(0).count("мороз"), 5);
+    }
+}
@@ -0,0 +1,567 @@
+use std::fmt::Error;
+use std::time::{Duration, SystemTime};
+
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::domain::embedding::{
+    ChunkEmbending, ChunkEmbendingRepo, Metric, ScoredChunk, VectorSearcher,
+};
+use crate::domain::filter::{DOC_ID_FIELD, Filter, Metadata, MetadataValue};
+
+const EMBENDING_ID_PROPERTY: &str = "embending_id";
+const MODEL_ID_PROPERTY: &str = "model_id";
+// Даты хранятся числами, поэтому их поля перечисляются отдельно,
+// иначе при чтении они вернутся как Int
+const TIMESTAMP_FIELDS_PROPERTY: &str = "timestamp_fields";
+
+// Эмбеддинги чанков в классе Weaviate: id объекта совпадает с id чанка,
+// метаданные чанка лежат в свойствах объекта рядом со служебными полями
+pub struct WeaviateIndex {
+    client: reqwest::Client,
+    url: String,
+    class: String,
+    metric: Metric,
+}
+
+impl WeaviateIndex {
+    pub fn new(url: &str, class: &str, metric: Metric) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            class: class.to_string(),
+            metric,
+        }
+    }
+
+    // Класс без встроенного векторизатора: векторы считает TextVectorizer
+    pub async fn create_class(&self) -> Result<(), Error> {
+        let body = json!({
+            "class": self.class,
+            "vectorizer": "none",
+            "vectorIndexConfig": { "distance": distance_name(self.metric) },
+        });
+        let response = self
+            .client
+            .post(format!("{}/v1/schema", self.url))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|_| Error)?;
+        response.error_for_status().map_err(|_| Error)?;
+        Ok(())
+    }
+
+    fn object_url(&self, chunk_id: Uuid) -> String {
+        format!("{}/v1/objects/{}/{}", self.url, self.class, chunk_id)
+    }
+}
+
+fn distance_name(metric: Metric) -> &'static str {
+    match metric {
+        Metric::Cosine => "cosine",
+        Metric::Dot => "dot",
+        Metric::L2 => "l2-squared",
+    }
+}
+
+// Weaviate возвращает расстояние, приводим его к близости из Metric::similarity
+fn score(metric: Metric, distance: f32) -> f32 {
+    match metric {
+        Metric::Cosine => 1.0 - distance,
+        Metric::Dot => -distance,
+        Metric::L2 => -distance.max(0.0).sqrt(),
+    }
+}
+
+fn unix_millis(time: &SystemTime) -> i64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+// Даты храним числом миллисекунд: так по ним работают фильтры диапазона,
+// а изменения в пределах одной секунды остаются различимы
+fn property_value(value: &MetadataValue) -> Value {
+    match value {
+        MetadataValue::Text(text) => json!(text),
+        MetadataValue::Int(number) => json!(number),
+        MetadataValue::Float(number) => json!(number),
+        MetadataValue::Bool(flag) => json!(flag),
+        MetadataValue::Uuid(id) => json!(id.to_string()),
+        MetadataValue::Timestamp(time) => json!(unix_millis(time)),
+    }
+}
+
+fn properties(embedding: &ChunkEmbending) -> Value {
+    let mut properties = serde_json::Map::new();
+    for (field, value) in &embedding.metadata {
+        properties.insert(field.clone(), property_value(value));
+    }
+    properties.insert(
+        EMBENDING_ID_PROPERTY.to_string(),
+        json!(embedding.id.to_string()),
+    );
+    properties.insert(MODEL_ID_PROPERTY.to_string(), json!(embedding.model_id));
+    let mut timestamp_fields: Vec<&String> = embedding
+        .metadata
+        .iter()
+        .filter(|(_, value)| matches!(value, MetadataValue::Timestamp(_)))
+        .map(|(field, _)| field)
+        .collect();
+    if !timestamp_fields.is_empty() {
+        timestamp_fields.sort();
+        properties.insert(
+            TIMESTAMP_FIELDS_PROPERTY.to_string(),
+            json!(timestamp_fields),
+        );
+    }
+    Value::Object(properties)
+}
+
+fn metadata_from_properties(properties: &serde_json::Map<String, Value>) -> Metadata {
+    let timestamp_fields: Vec<&str> = properties
+        .get(TIMESTAMP_FIELDS_PROPERTY)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+
+    let mut metadata = Metadata::new();
+    for (field, value) in properties {
+        let value = match (field.as_str(), value) {
+            (EMBENDING_ID_PROPERTY | MODEL_ID_PROPERTY | TIMESTAMP_FIELDS_PROPERTY, _) => continue,
+            (DOC_ID_FIELD, Value::String(id)) => match Uuid::parse_str(id) {
+                Ok(id) => MetadataValue::Uuid(id),
+                Err(_) => MetadataValue::Text(id.clone()),
+            },
+            (field, Value::Number(millis)) if timestamp_fields.contains(&field) => {
+                MetadataValue::Timestamp(
+                    SystemTime::UNIX_EPOCH
+                        + Duration::from_millis(millis.as_u64().unwrap_or_default()),
+                )
+            }
+            (_, Value::String(text)) => MetadataValue::Text(text.clone()),
+            (_, Value::Bool(flag)) => MetadataValue::Bool(*flag),
+            (_, Value::Number(number)) => match number.as_i64() {
+                Some(int) => MetadataValue::Int(int),
+                None => MetadataValue::Float(number.as_f64().unwrap_or_default()),
+            },
+            _ => continue,
+        };
+        metadata.insert(field.clone(), value);
+    }
+    metadata
+}
+
+fn graphql_value(value: &MetadataValue) -> String {
+    match value {
+        MetadataValue::Text(text) => format!("valueText: {}", json!(text)),
+        MetadataValue::Uuid(id) => format!("valueText: \"{id}\""),
+        MetadataValue::Int(number) => format!("valueInt: {number}"),
+        MetadataValue::Timestamp(time) => format!("valueInt: {}", unix_millis(time)),
+        MetadataValue::Float(number) => format!("valueNumber: {number:?}"),
+        MetadataValue::Bool(flag) => format!("valueBoolean: {flag}"),
+    }
+}
+
+fn condition(field: &str, operator: &str, value: &MetadataValue) -> String {
+    format!(
+        "{{path: [{}], operator: {operator}, {}}}",
+        json!(field),
+        graphql_value(value)
+    )
+}
+
+fn is_null(field: &str, null: bool) -> String {
+    format!(
+        "{{path: [{}], operator: IsNull, valueBoolean: {null}}}",
+        json!(field)
+    )
+}
+
+fn combine(operator: &str, operands: Vec<String>) -> String {
+    format!(
+        "{{operator: {operator}, operands: [{}]}}",
+        operands.join(", ")
+    )
+}
+
+// Перевод фильтра в аргумент where языка GraphQL Weaviate
+fn where_clause(filter: &Filter) -> String {
+    match filter {
+        Filter::Eq(field, value) => condition(field, "Equal", value),
+        // Пустой список не совпадает ни с чем, а Or без операндов Weaviate не примет
+        Filter::In(field, values) if values.is_empty() => {
+            combine("And", vec![is_null(field, true), is_null(field, false)])
+        }
+        Filter::In(field, values) => combine(
+            "Or",
+            values
+                .iter()
+                .map(|value| condition(field, "Equal", value))
+                .collect(),
+        ),
+        Filter::Range { field, gte, lte } => {
+            let mut bounds = Vec::new();
+            if let Some(gte) = gte {
+                bounds.push(condition(field, "GreaterThanEqual", gte));
+            }
+            if let Some(lte) = lte {
+                bounds.push(condition(field, "LessThanEqual", lte));
+            }
+            match bounds.len() {
+                0 => is_null(field, false),
+                1 => bounds.remove(0),
+                _ => combine("And", bounds),
+            }
+        }
+        Filter::And(filters) => combine("And", filters.iter().map(where_clause).collect()),
+    }
+}
+
+fn near_vector_query(class: &str, vector: &[f32], limit: usize, filter: Option<&Filter>) -> String {
+    let filter = filter
+        .map(|f| format!(", where: {}", where_clause(f)))
+        .unwrap_or_default();
+    format!(
+        "{{ Get {{ {class}(nearVector: {{vector: {}}}, limit: {limit}{filter}) {{ _additional {{ id distance }} }} }} }}",
+        json!(vector)
+    )
+}
+
+fn parse_search(class: &str, metric: Metric, body: &Value) -> Result<Vec<ScoredChunk>, Error> {
+    if body.get("errors").is_some_and(|e| !e.is_null()) {
+        return Err(Error);
+    }
+    let objects = body["data"]["Get"][class].as_array().ok_or(Error)?;
+    objects
+        .iter()
+        .map(|object| {
+            let additional = &object["_additional"];
+            let chunk_id =
+                Uuid::parse_str(additional["id"].as_str().ok_or(Error)?).map_err(|_| Error)?;
+            let distance = additional["distance"].as_f64().ok_or(Error)? as f32;
+            Ok(ScoredChunk {
+                chunk_id,
+                score: score(metric, distance),
+            })
+        })
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl ChunkEmbendingRepo for WeaviateIndex {
+    async fn save(&self, embedding: &ChunkEmbending) -> Result<(), Error> {
+        // Пакетная вставка перезаписывает объект с тем же id
+        let body = json!({
+            "objects": [{
+                "class": self.class,
+                "id": embedding.chunk_id.to_string(),
+                "vector": embedding.vec,
+                "properties": properties(embedding),
+            }]
+        });
+        let response = self
+            .client
+            .post(format!("{}/v1/batch/objects", self.url))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|_| Error)?
+            .error_for_status()
+            .map_err(|_| Error)?;
+
+        let results: Value = response.json().await.map_err(|_| Error)?;
+        let failed = results
+            .as_array()
+            .into_iter()
+            .flatten()
+            .any(|r| !r["result"]["errors"].is_null());
+        if failed {
+            return Err(Error);
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, chunk_id: Uuid) -> Result<(), Error> {
+        let response = self
+            .client
+            .delete(self.object_url(chunk_id))
+            .send()
+            .await
+            .map_err(|_| Error)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        response.error_for_status().map_err(|_| Error)?;
+        Ok(())
+    }
+
+    async fn read(&self, chunk_id: Uuid) -> Result<ChunkEmbending, Error> {
+        let object: Value = self
+            .client
+            .get(self.object_url(chunk_id))
+            .query(&[("include", "vector")])
+            .send()
+            .await
+            .map_err(|_| Error)?
+            .error_for_status()
+            .map_err(|_| Error)?
+            .json()
+            .await
+            .map_err(|_| Error)?;
+
+        let properties = object["properties"].as_object().ok_or(Error)?;
+        let id = properties
+            .get(EMBENDING_ID_PROPERTY)
+            .and_then(Value::as_str)
+            .ok_or(Error)?;
+        Ok(ChunkEmbending {
+            id: Uuid::parse_str(id).map_err(|_| Error)?,
+            chunk_id,
+            model_id: properties
+                .get(MODEL_ID_PROPERTY)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            vec: serde_json::from_value(object["vector"].clone()).map_err(|_| Error)?,
+            metadata: metadata_from_properties(properties),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl VectorSearcher for WeaviateIndex {
+    fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    async fn search_similar<'a>(
+        &self,
+        vector: &[f32],
+        top_k: usize,
+        min_score: Option<f32>,
+        filter: Option<&'a Filter>,
+    ) -> Result<Vec<ScoredChunk>, Error> {
+        let query = near_vector_query(&self.class, vector, top_k, filter);
+        let body: Value = self
+            .client
+            .post(format!("{}/v1/graphql", self.url))
+            .json(&json!({ "query": query }))
+            .send()
+            .await
+            .map_err(|_| Error)?
+            .error_for_status()
+            .map_err(|_| Error)?
+            .json()
+            .await
+            .map_err(|_| Error)?;
+
+        let mut found = parse_search(&self.class, self.metric, &body)?;
+        if let Some(min_score) = min_score {
+            found.retain(|c| c.score >= min_score);
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::filter::UPDATED_AT_FIELD;
+    use crate::testing::serve;
+
+    #[test]
+    fn test_where_clause_from_filter() {
+        let doc_id = Uuid::from_u128(1);
+        let filter = Filter::And(vec![
+            Filter::document(doc_id),
+            Filter::one_of("tag", ["faq", "news"]),
+            Filter::updated_after(SystemTime::UNIX_EPOCH + Duration::from_secs(100)),
+        ]);
+
+        let query = near_vector_query("Chunk", &[0.5, 1.0], 3, Some(&filter));
+
+        assert_eq!(
+            query,
+            "{ Get { Chunk(nearVector: {vector: [0.5,1.0]}, limit: 3, where: \
+             {operator: And, operands: [\
+             {path: [\"doc_id\"], operator: Equal, valueText: \"00000000-0000-0000-0000-000000000001\"}, \
+             {operator: Or, operands: [\
+             {path: [\"tag\"], operator: Equal, valueText: \"faq\"}, \
+             {path: [\"tag\"], operator: Equal, valueText: \"news\"}]}, \
+             {path: [\"updated_at\"], operator: GreaterThanEqual, valueInt: 100000}]}) \
+             { _additional { id distance } } } }"
+        );
+    }
+
+    #[test]
+    fn test_parse_search_converts_distance_to_score() {
+        let chunk_id = Uuid::new_v4();
+        let body = json!({
+            "data": { "Get": { "Chunk": [
+                { "_additional": { "id": chunk_id.to_string(), "distance": 0.25 } }
+            ] } }
+        });
+
+        let found = parse_search("Chunk", Metric::Cosine, &body).unwrap();
+
+        assert_eq!(
+            found,
+            vec![ScoredChunk {
+                chunk_id,
+                score: 0.75
+            }]
+        );
+        assert!(parse_search("Chunk", Metric::Cosine, &json!({ "errors": [{}] })).is_err());
+    }
+
+    #[test]
+    fn test_metadata_survives_properties_round_trip() {
+        let doc_id = Uuid::new_v4();
+        let embedding = ChunkEmbending {
+            id: Uuid::new_v4(),
+            chunk_id: Uuid::new_v4(),
+            model_id: "model".into(),
+            vec: vec![0.1],
+            metadata: Metadata::from([
+                (DOC_ID_FIELD.to_string(), doc_id.into()),
+                (
+                    UPDATED_AT_FIELD.to_string(),
+                    (SystemTime::UNIX_EPOCH + Duration::from_millis(42_250)).into(),
+                ),
+                (
+                    "published_at".to_string(),
+                    (SystemTime::UNIX_EPOCH + Duration::from_secs(7)).into(),
+                ),
+                ("tenant".to_string(), "acme".into()),
+                ("priority".to_string(), 2i64.into()),
+            ]),
+        };
+
+        let properties = properties(&embedding);
+
+        assert_eq!(
+            metadata_from_properties(properties.as_object().unwrap()),
+            embedding.metadata
+        );
+    }
+
+    #[test]
+    fn test_timestamp_range_within_one_second() {
+        let at = |millis| {
+            MetadataValue::Timestamp(SystemTime::UNIX_EPOCH + Duration::from_millis(millis))
+        };
+        let filter = Filter::updated_after(SystemTime::UNIX_EPOCH + Duration::from_millis(100_500));
+
+        // Граница и оба значения в одной секунде, но по разные стороны от границы
+        assert!(where_clause(&filter).ends_with("valueInt: 100500}"));
+        assert_eq!(property_value(&at(100_200)), json!(100_200));
+        assert_eq!(property_value(&at(100_700)), json!(100_700));
+    }
+
+    #[test]
+    fn test_empty_one_of_matches_nothing() {
+        let filter = Filter::In("tag".into(), vec![]);
+
+        assert_eq!(
+            where_clause(&filter),
+            "{operator: And, operands: [\
+             {path: [\"tag\"], operator: IsNull, valueBoolean: true}, \
+             {path: [\"tag\"], operator: IsNull, valueBoolean: false}]}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_save_read_and_delete_over_http() {
+        let chunk_id = Uuid::from_u128(7);
+        let embedding_id = Uuid::from_u128(8);
+        let (url, server) = serve(vec![
+            (200, r#"[{"result":{}}]"#),
+            (
+                200,
+                r#"{"id":"00000000-0000-0000-0000-000000000007","vector":[0.5,1.0],
+                    "properties":{"embending_id":"00000000-0000-0000-0000-000000000008",
+                    "model_id":"model","updated_at":42250,"timestamp_fields":["updated_at"]}}"#,
+            ),
+            (404, "{}"),
+        ])
+        .await;
+        let index = WeaviateIndex::new(&url, "Chunk", Metric::Cosine);
+        let embedding = ChunkEmbending {
+            id: embedding_id,
+            chunk_id,
+            model_id: "model".into(),
+            vec: vec![0.5, 1.0],
+            metadata: Metadata::from([(
+                UPDATED_AT_FIELD.to_string(),
+                (SystemTime::UNIX_EPOCH + Duration::from_millis(42_250)).into(),
+            )]),
+        };
+
+        index.save(&embedding).await.unwrap();
+        let stored = index.read(chunk_id).await.unwrap();
+        assert_eq!(stored.id, embedding_id);
+        assert_eq!(stored.vec, embedding.vec);
+        assert_eq!(stored.metadata, embedding.metadata);
+        // Удаление уже удалённого объекта не ошибка
+        index.delete(chunk_id).await.unwrap();
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests[0].0, "POST /v1/batch/objects HTTP/1.1");
+        let object = &requests[0].1["objects"][0];
+        assert_eq!(object["id"], chunk_id.to_string());
+        assert_eq!(object["properties"]["updated_at"], 42_250);
+        assert_eq!(
+            requests[1].0,
+            format!("GET /v1/objects/Chunk/{chunk_id}?include=vector HTTP/1.1")
+        );
+        assert_eq!(
+            requests[2].0,
+            format!("DELETE /v1/objects/Chunk/{chunk_id} HTTP/1.1")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_over_http_applies_filter_and_min_score() {
+        let (url, server) = serve(vec![
+            (
+                200,
+                r#"{"data":{"Get":{"Chunk":[
+                    {"_additional":{"id":"00000000-0000-0000-0000-000000000001","distance":0.1}},
+                    {"_additional":{"id":"00000000-0000-0000-0000-000000000002","distance":0.6}}]}}}"#,
+            ),
+            (500, "{}"),
+        ])
+        .await;
+        let index = WeaviateIndex::new(&url, "Chunk", Metric::Cosine);
+        let filter = Filter::eq("tenant", "acme");
+
+        let found = index
+            .search_similar(&[1.0, 0.0], 2, Some(0.5), Some(&filter))
+            .await
+            .unwrap();
+        assert_eq!(
+            found,
+            vec![ScoredChunk {
+                chunk_id: Uuid::from_u128(1),
+                score: 0.9
+            }]
+        );
+        assert!(
+            index
+                .search_similar(&[1.0, 0.0], 2, None, None)
+                .await
+                .is_err()
+        );
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests[0].0, "POST /v1/graphql HTTP/1.1");
+        assert_eq!(
+            requests[0].1["query"],
+            near_vector_query("Chunk", &[1.0, 0.0], 2, Some(&filter))
+        );
+    }
+}
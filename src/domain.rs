@@ -1,4 +1,13 @@
+pub mod conversation;
 pub mod document;
 pub mod embedding;
+pub mod feedback;
+pub mod filter;
+pub mod groundedness;
+pub mod lexical;
+pub mod prompt;
 pub mod question;
+pub mod reembedding;
+pub mod rerank;
+pub mod tokenizer;
 pub mod unswer;
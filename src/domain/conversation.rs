@@ -0,0 +1,75 @@
+use std::fmt::Error;
+use std::time::SystemTime;
+
+use uuid::Uuid;
+
+use crate::domain::prompt::HistoryTurn;
+
+// Вопрос разговора и ответ на него; ответа нет, пока вопрос не обработан
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConversationTurn {
+    pub question_id: Uuid,
+    pub question: String,
+    pub unswer_id: Option<Uuid>,
+    pub unswer: Option<String>,
+}
+
+pub struct Conversation {
+    pub id: Uuid,
+    pub created_at: SystemTime,
+    // В порядке вопросов
+    pub turns: Vec<ConversationTurn>,
+}
+
+impl Conversation {
+    pub fn new() -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            created_at: SystemTime::now(),
+            turns: Vec::new(),
+        }
+    }
+
+    // Отвеченные вопросы, заданные раньше question_id (все, если его нет в разговоре)
+    pub fn history(&self, question_id: Uuid) -> Vec<HistoryTurn> {
+        self.turns
+            .iter()
+            .take_while(|turn| turn.question_id != question_id)
+            .filter_map(|turn| {
+                Some(HistoryTurn {
+                    question: turn.question.clone(),
+                    unswer: turn.unswer.clone()?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Default for Conversation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[mockall::automock]
+#[async_trait::async_trait]
+pub trait ConversationRepo: Send + Sync {
+    async fn save(&self, conversation: &Conversation) -> Result<(), Error>;
+    async fn read(&self, conversation_id: Uuid) -> Result<Conversation, Error>;
+    async fn update(&self, conversation: &Conversation) -> Result<(), Error>;
+    async fn delete(&self, conversation_id: Uuid) -> Result<(), Error>;
+    // Изменения ходов без чтения всего разговора: хранилище выполняет их атомарно,
+    // поэтому параллельные вопросы и ответы одного разговора не затирают друг друга.
+    // Добавляет ход в конец разговора
+    async fn append_turn(
+        &self,
+        conversation_id: Uuid,
+        turn: &ConversationTurn,
+    ) -> Result<(), Error>;
+    // Заменяет ход с тем же question_id; если такого хода нет, добавляет его в конец
+    async fn update_turn(
+        &self,
+        conversation_id: Uuid,
+        turn: &ConversationTurn,
+    ) -> Result<(), Error>;
+}
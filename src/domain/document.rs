@@ -1,12 +1,20 @@
 use std::fmt::Error;
+use std::time::SystemTime;
 
 use uuid::Uuid;
 
+use crate::domain::filter::{DOC_ID_FIELD, Metadata, UPDATED_AT_FIELD};
+
 #[derive(Clone, Debug)]
 pub struct Document {
     pub id: Uuid,
     pub version: usize,
     pub text: String,
+    // Краткое содержание, если его подготовили при загрузке
+    pub summary: Option<String>,
+    // Произвольные поля для фильтрации: арендатор, теги и т.п.
+    pub metadata: Metadata,
+    pub updated_at: SystemTime,
 }
 
 #[mockall::automock]
@@ -23,13 +31,26 @@ impl Document {
         Self {
             id: Uuid::new_v4(),
             version: 1,
-            text: text,
+            text,
+            summary: None,
+            metadata: Metadata::new(),
+            updated_at: SystemTime::now(),
         }
     }
 
     pub fn update(&mut self, new_text: String) {
         self.version += 1;
         self.text = new_text;
+        self.summary = None;
+        self.updated_at = SystemTime::now();
+    }
+
+    // Метаданные, которые наследует каждый чанк документа
+    pub fn chunk_metadata(&self) -> Metadata {
+        let mut metadata = self.metadata.clone();
+        metadata.insert(DOC_ID_FIELD.to_string(), self.id.into());
+        metadata.insert(UPDATED_AT_FIELD.to_string(), self.updated_at.into());
+        metadata
     }
 }
 
@@ -37,15 +58,20 @@ impl Document {
 pub struct Chunk {
     pub id: Uuid,
     pub doc_id: Uuid,
+    // Порядковый номер чанка в документе
+    pub position: usize,
     pub text: String,
+    pub metadata: Metadata,
 }
 
 impl Chunk {
     pub fn new(doc_id: Uuid, text: String) -> Self {
         Self {
             id: Uuid::new_v4(),
-            doc_id: doc_id,
-            text: text,
+            doc_id,
+            position: 0,
+            text,
+            metadata: Metadata::from([(DOC_ID_FIELD.to_string(), doc_id.into())]),
         }
     }
 }
@@ -56,7 +82,24 @@ pub trait ChunkRepo: Send + Sync {
     async fn save(&self, chunk: &Chunk) -> Result<(), Error>;
     async fn delete(&self, chunk_id: Uuid) -> Result<(), Error>;
     async fn read(&self, chunk_id: Uuid) -> Result<Chunk, Error>;
+    // None — чанка нет; ошибка — его не удалось прочитать
+    async fn find(&self, chunk_id: Uuid) -> Result<Option<Chunk>, Error>;
+    // Чтение нескольких чанков за один запрос; результат в порядке ids,
+    // отсутствующие чанки пропускаются. По умолчанию чанки читаются параллельно
+    // через find, ошибка чтения любого из них возвращается
+    async fn read_many(&self, chunk_ids: &[Uuid]) -> Result<Vec<Chunk>, Error> {
+        let reads = chunk_ids.iter().map(|chunk_id| self.find(*chunk_id));
+        let found = futures::future::join_all(reads)
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(found.into_iter().flatten().collect())
+    }
+    // Чанки документа в порядке position
     async fn read_by_doc(&self, doc_id: Uuid) -> Result<Vec<Chunk>, Error>;
+    // Постраничный обход всех чанков в порядке возрастания id
+    async fn list(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<Chunk>, Error>;
+    async fn count(&self) -> Result<usize, Error>;
 }
 
 #[cfg(test)]
@@ -73,4 +116,70 @@ mod tests {
         assert_eq!(document.text, check_text.to_string());
         assert_eq!(document.version, 2);
     }
+
+    // Хранилище только с find, чтобы проверить read_many по умолчанию;
+    // чанк unreadable есть, но не читается
+    struct ReadOnlyChunks {
+        chunks: Vec<Chunk>,
+        unreadable: Option<Uuid>,
+    }
+
+    #[async_trait::async_trait]
+    impl ChunkRepo for ReadOnlyChunks {
+        async fn save(&self, _chunk: &Chunk) -> Result<(), Error> {
+            Err(Error)
+        }
+        async fn delete(&self, _chunk_id: Uuid) -> Result<(), Error> {
+            Err(Error)
+        }
+        async fn read(&self, chunk_id: Uuid) -> Result<Chunk, Error> {
+            self.find(chunk_id).await?.ok_or(Error)
+        }
+        async fn find(&self, chunk_id: Uuid) -> Result<Option<Chunk>, Error> {
+            if self.unreadable == Some(chunk_id) {
+                return Err(Error);
+            }
+            Ok(self.chunks.iter().find(|c| c.id == chunk_id).cloned())
+        }
+        async fn read_by_doc(&self, _doc_id: Uuid) -> Result<Vec<Chunk>, Error> {
+            Err(Error)
+        }
+        async fn list(&self, _after: Option<Uuid>, _limit: usize) -> Result<Vec<Chunk>, Error> {
+            Err(Error)
+        }
+        async fn count(&self) -> Result<usize, Error> {
+            Err(Error)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_many_keeps_order_and_skips_missing() {
+        let doc_id = Uuid::new_v4();
+        let chunks = vec![
+            Chunk::new(doc_id, "первый".into()),
+            Chunk::new(doc_id, "второй".into()),
+        ];
+        let ids = [chunks[1].id, Uuid::new_v4(), chunks[0].id];
+        let repo = ReadOnlyChunks {
+            chunks,
+            unreadable: None,
+        };
+
+        let found = repo.read_many(&ids).await.unwrap();
+
+        let texts: Vec<&str> = found.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts, vec!["второй", "первый"]);
+    }
+
+    #[tokio::test]
+    async fn test_read_many_fails_on_read_error() {
+        let chunk = Chunk::new(Uuid::new_v4(), "первый".into());
+        let ids = [chunk.id, Uuid::new_v4()];
+        let repo = ReadOnlyChunks {
+            unreadable: Some(chunk.id),
+            chunks: vec![chunk],
+        };
+
+        assert!(repo.read_many(&ids).await.is_err());
+    }
 }
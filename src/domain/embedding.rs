@@ -1,13 +1,22 @@
+use std::collections::HashSet;
 use std::fmt::Error;
+use std::sync::{Arc, RwLock};
 
 use uuid::Uuid;
 
-use crate::domain::{document::Chunk, question::Question};
+use crate::domain::{
+    document::{Chunk, ChunkRepo},
+    filter::{Filter, Metadata},
+    question::Question,
+};
 
 pub struct ChunkEmbending {
     pub id: Uuid,
     pub chunk_id: Uuid,
-    pub vec: Vec<f64>,
+    pub model_id: String,
+    pub vec: Vec<f32>,
+    // Копия метаданных чанка, по которой поисковик фильтрует результаты
+    pub metadata: Metadata,
 }
 
 impl ChunkEmbending {
@@ -19,7 +28,9 @@ impl ChunkEmbending {
             Ok(vec) => Ok(Self {
                 id: Uuid::new_v4(),
                 chunk_id: chunk.id,
-                vec: vec,
+                model_id: vectorizer.model_id(),
+                vec,
+                metadata: chunk.metadata.clone(),
             }),
             Err(err) => Err(err),
         }
@@ -29,13 +40,69 @@ impl ChunkEmbending {
 #[mockall::automock]
 #[async_trait::async_trait]
 pub trait TextVectorizer: Send + Sync {
-    async fn vectorize(&self, text: &str) -> Result<Vec<f64>, Error>;
+    async fn vectorize(&self, text: &str) -> Result<Vec<f32>, Error>;
+    fn model_id(&self) -> String;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Metric {
+    #[default]
+    Cosine,
+    Dot,
+    L2,
+}
+
+impl Metric {
+    // Чем больше значение, тем ближе векторы
+    pub fn similarity(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Metric::Cosine => {
+                let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+                let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+                let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+                if norm_a == 0.0 || norm_b == 0.0 {
+                    0.0
+                } else {
+                    dot / (norm_a * norm_b)
+                }
+            }
+            Metric::Dot => a.iter().zip(b).map(|(x, y)| x * y).sum(),
+            Metric::L2 => -a
+                .iter()
+                .zip(b)
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum::<f32>()
+                .sqrt(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScoredQuestion {
+    pub question_id: Uuid,
+    pub score: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScoredChunk {
+    pub chunk_id: Uuid,
+    // Близость по метрике поисковика, чем больше — тем релевантнее
+    pub score: f32,
 }
 
 #[mockall::automock]
 #[async_trait::async_trait]
 pub trait VectorSearcher: Send + Sync {
-    async fn search_similar(&self, vector: &Vec<f64>, top_k: usize) -> Result<Vec<Uuid>, Error>;
+    fn metric(&self) -> Metric;
+    // Результаты упорядочены по убыванию score; ниже min_score отбрасываются,
+    // чанки, не подходящие под filter, не учитываются при отборе top_k
+    async fn search_similar<'a>(
+        &self,
+        vector: &[f32],
+        top_k: usize,
+        min_score: Option<f32>,
+        filter: Option<&'a Filter>,
+    ) -> Result<Vec<ScoredChunk>, Error>;
 }
 
 #[mockall::automock]
@@ -43,13 +110,193 @@ pub trait VectorSearcher: Send + Sync {
 pub trait ChunkEmbendingRepo: Send + Sync {
     async fn save(&self, embedding: &ChunkEmbending) -> Result<(), Error>;
     async fn delete(&self, chunk_id: Uuid) -> Result<(), Error>;
+    // Вектор возвращается в том виде, в каком его хранит индекс: он может быть
+    // нормализован или восстановлен после квантования. Направление сохраняется
+    // с точностью до квантования, поэтому сравнивать его можно метрикой поисковика,
+    // но не поэлементно с сохранённым.
     async fn read(&self, chunk_id: Uuid) -> Result<ChunkEmbending, Error>;
 }
 
+// Векторный индекс одной модели эмбеддингов: хранилище + поиск
+#[derive(Clone)]
+pub struct VectorIndex {
+    pub model_id: String,
+    pub embending_repo: Arc<dyn ChunkEmbendingRepo>,
+    pub searcher: Arc<dyn VectorSearcher>,
+}
+
+#[mockall::automock]
+#[async_trait::async_trait]
+pub trait VectorIndexRegistry: Send + Sync {
+    // Открывает (или создаёт) индекс, отдельный для каждой модели
+    async fn open(&self, model_id: &str) -> Result<VectorIndex, Error>;
+}
+
+// Модель эмбеддингов вместе с её индексом
+#[derive(Clone)]
+pub struct EmbeddingModel {
+    pub vectorizer: Arc<dyn TextVectorizer>,
+    pub index: VectorIndex,
+}
+
+struct ActiveState {
+    current: EmbeddingModel,
+    // Модель, на которую идёт переиндексация
+    next: Option<EmbeddingModel>,
+    // Чанки, записанные или удалённые с начала переиндексации
+    changed: HashSet<Uuid>,
+}
+
+// Модель эмбеддингов, общая для всех сервисов: она векторизует вопросы и чанки,
+// хранит эмбеддинги и ищет по ним. Векторизатор, хранилище и поиск
+// переключаются на другую модель одновременно.
+pub struct ActiveEmbeddingModel {
+    state: RwLock<ActiveState>,
+    // Источник текста чанков, вектор которых посчитан уже сменённой моделью
+    chunk_repo: Arc<dyn ChunkRepo>,
+}
+
+impl ActiveEmbeddingModel {
+    pub fn new(model: EmbeddingModel, chunk_repo: Arc<dyn ChunkRepo>) -> Self {
+        Self {
+            state: RwLock::new(ActiveState {
+                current: model,
+                next: None,
+                changed: HashSet::new(),
+            }),
+            chunk_repo,
+        }
+    }
+
+    pub fn model_id(&self) -> String {
+        self.state.read().unwrap().current.index.model_id.clone()
+    }
+
+    // С этого момента запоминаются чанки, которые нужно дописать в индекс next
+    pub fn begin_switch(&self, next: EmbeddingModel) {
+        let mut state = self.state.write().unwrap();
+        state.next = Some(next);
+        state.changed.clear();
+    }
+
+    // Чанки, изменённые с прошлого вызова
+    pub fn take_changed(&self) -> Vec<Uuid> {
+        self.state.write().unwrap().changed.drain().collect()
+    }
+
+    // Переключает на next, только если с прошлого take_changed ничего не изменилось;
+    // false — изменения есть, их нужно дописать и попробовать снова
+    pub fn try_switch(&self) -> bool {
+        let mut state = self.state.write().unwrap();
+        if !state.changed.is_empty() {
+            return false;
+        }
+        if let Some(next) = state.next.take() {
+            state.current = next;
+        }
+        true
+    }
+
+    fn current(&self) -> EmbeddingModel {
+        self.state.read().unwrap().current.clone()
+    }
+
+    // Запоминает удалённый чанк и отдаёт хранилище текущей модели
+    fn track(&self, chunk_id: Uuid) -> Arc<dyn ChunkEmbendingRepo> {
+        self.state.write().unwrap().track(chunk_id)
+    }
+
+    // Как track, но если вектор посчитан другой моделью, вместо хранилища отдаётся
+    // векторизатор текущей: модель сменили, пока чанк векторизовался.
+    // Проверка и запоминание идут под одной блокировкой, чтобы между ними не вклинилось
+    // переключение.
+    fn track_save(
+        &self,
+        embedding: &ChunkEmbending,
+    ) -> Result<Arc<dyn ChunkEmbendingRepo>, Arc<dyn TextVectorizer>> {
+        let mut state = self.state.write().unwrap();
+        if embedding.model_id != state.current.index.model_id {
+            return Err(state.current.vectorizer.clone());
+        }
+        Ok(state.track(embedding.chunk_id))
+    }
+}
+
+impl ActiveState {
+    // Чанк запоминается до записи, иначе переключение между ними потеряло бы его
+    fn track(&mut self, chunk_id: Uuid) -> Arc<dyn ChunkEmbendingRepo> {
+        if self.next.is_some() {
+            self.changed.insert(chunk_id);
+        }
+        self.current.index.embending_repo.clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl TextVectorizer for ActiveEmbeddingModel {
+    async fn vectorize(&self, text: &str) -> Result<Vec<f32>, Error> {
+        self.current().vectorizer.vectorize(text).await
+    }
+
+    fn model_id(&self) -> String {
+        ActiveEmbeddingModel::model_id(self)
+    }
+}
+
+#[async_trait::async_trait]
+impl ChunkEmbendingRepo for ActiveEmbeddingModel {
+    async fn save(&self, embedding: &ChunkEmbending) -> Result<(), Error> {
+        let mut revectorized = None;
+        loop {
+            let embedding = revectorized.as_ref().unwrap_or(embedding);
+            match self.track_save(embedding) {
+                Ok(repo) => return repo.save(embedding).await,
+                // Вектор сменённой модели в текущий индекс не подходит: считаем заново
+                Err(vectorizer) => {
+                    let chunk = self.chunk_repo.read(embedding.chunk_id).await?;
+                    revectorized = Some(ChunkEmbending::new(&chunk, vectorizer.as_ref()).await?);
+                }
+            }
+        }
+    }
+
+    async fn delete(&self, chunk_id: Uuid) -> Result<(), Error> {
+        self.track(chunk_id).delete(chunk_id).await
+    }
+
+    async fn read(&self, chunk_id: Uuid) -> Result<ChunkEmbending, Error> {
+        self.current().index.embending_repo.read(chunk_id).await
+    }
+}
+
+#[async_trait::async_trait]
+impl VectorSearcher for ActiveEmbeddingModel {
+    fn metric(&self) -> Metric {
+        self.current().index.searcher.metric()
+    }
+
+    async fn search_similar<'a>(
+        &self,
+        vector: &[f32],
+        top_k: usize,
+        min_score: Option<f32>,
+        filter: Option<&'a Filter>,
+    ) -> Result<Vec<ScoredChunk>, Error> {
+        // Берём снимок, чтобы не держать блокировку во время поиска
+        let searcher = self.current().index.searcher;
+        searcher
+            .search_similar(vector, top_k, min_score, filter)
+            .await
+    }
+}
+
 pub struct QuestionEmbending {
     pub id: Uuid,
     pub question_id: Uuid,
-    pub vec: Vec<f64>,
+    // Индекс в Question.rewrites; None — эмбеддинг исходного текста вопроса
+    pub rewrite: Option<usize>,
+    pub model_id: String,
+    pub vec: Vec<f32>,
 }
 
 impl QuestionEmbending {
@@ -57,15 +304,34 @@ impl QuestionEmbending {
         question: &Question,
         vectorizer: &dyn TextVectorizer,
     ) -> Result<QuestionEmbending, Error> {
-        match vectorizer.vectorize(question.text.as_str()).await {
+        match vectorizer.vectorize(question.search_text()).await {
             Ok(vec) => Ok(Self {
                 id: Uuid::new_v4(),
                 question_id: question.id,
-                vec: vec,
+                rewrite: None,
+                model_id: vectorizer.model_id(),
+                vec,
             }),
             Err(err) => Err(err),
         }
     }
+
+    pub async fn for_rewrite(
+        question: &Question,
+        rewrite: usize,
+        vectorizer: &dyn TextVectorizer,
+    ) -> Result<QuestionEmbending, Error> {
+        let vec = vectorizer
+            .vectorize(question.rewrites[rewrite].text.as_str())
+            .await?;
+        Ok(Self {
+            id: Uuid::new_v4(),
+            question_id: question.id,
+            rewrite: Some(rewrite),
+            model_id: vectorizer.model_id(),
+            vec,
+        })
+    }
 }
 
 #[mockall::automock]
@@ -73,5 +339,16 @@ impl QuestionEmbending {
 pub trait QuestionEmbeddingRepo: Send + Sync {
     async fn save(&self, embedding: &QuestionEmbending) -> Result<(), Error>;
     async fn delete(&self, question_id: Uuid) -> Result<(), Error>;
+    // Эмбеддинг исходного текста вопроса
     async fn read(&self, question_id: Uuid) -> Result<QuestionEmbending, Error>;
+    // Эмбеддинги переформулировок в порядке Question.rewrites
+    async fn read_rewrites(&self, question_id: Uuid) -> Result<Vec<QuestionEmbending>, Error>;
+    // Вопросы, чей исходный текст ближе всего к vector, по убыванию близости.
+    // Сравниваются только эмбеддинги модели model_id: векторы разных моделей несравнимы
+    async fn search_similar(
+        &self,
+        vector: &[f32],
+        model_id: &str,
+        top_k: usize,
+    ) -> Result<Vec<ScoredQuestion>, Error>;
 }
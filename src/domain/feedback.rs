@@ -0,0 +1,59 @@
+use std::fmt::Error;
+use std::time::SystemTime;
+
+use uuid::Uuid;
+
+use crate::domain::unswer::Unswer;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub enum Rating {
+    Up,
+    Down,
+}
+
+// Отзыв пользователя на ответ. Контекст и процитированные документы копируются
+// из ответа, чтобы отчёты строились без чтения ответов.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Feedback {
+    pub id: Uuid,
+    pub unswer_id: Uuid,
+    pub question_id: Uuid,
+    pub created_at: SystemTime,
+    pub rating: Rating,
+    // Исправленный ответ или замечание своими словами
+    pub correction: Option<String>,
+    pub context_chunks_id: Vec<Uuid>,
+    // Документы, на которые ссылается текст ответа, без повторов
+    pub cited_doc_ids: Vec<Uuid>,
+}
+
+impl Feedback {
+    pub fn new(unswer: &Unswer, rating: Rating, correction: Option<String>) -> Self {
+        let mut cited_doc_ids: Vec<Uuid> = Vec::new();
+        for citation in &unswer.citations {
+            if !cited_doc_ids.contains(&citation.doc_id) {
+                cited_doc_ids.push(citation.doc_id);
+            }
+        }
+        Self {
+            id: Uuid::new_v4(),
+            unswer_id: unswer.id,
+            question_id: unswer.question_id,
+            created_at: SystemTime::now(),
+            rating,
+            correction,
+            context_chunks_id: unswer.context_chunks_id.clone(),
+            cited_doc_ids,
+        }
+    }
+}
+
+#[mockall::automock]
+#[async_trait::async_trait]
+pub trait FeedbackRepo: Send + Sync {
+    async fn save(&self, feedback: &Feedback) -> Result<(), Error>;
+    // Все отзывы на ответ, от старых к новым
+    async fn list_by_unswer(&self, unswer_id: Uuid) -> Result<Vec<Feedback>, Error>;
+    // Страница отзывов в порядке id, начиная после after
+    async fn list(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<Feedback>, Error>;
+}
@@ -0,0 +1,181 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use uuid::Uuid;
+
+// Служебные поля, которые есть в метаданных каждого чанка
+pub const DOC_ID_FIELD: &str = "doc_id";
+pub const UPDATED_AT_FIELD: &str = "updated_at";
+// Необязательное поле метаданных документа с его названием
+pub const TITLE_FIELD: &str = "title";
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum MetadataValue {
+    Text(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Uuid(Uuid),
+    Timestamp(SystemTime),
+}
+
+pub type Metadata = BTreeMap<String, MetadataValue>;
+
+impl PartialOrd for MetadataValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        use MetadataValue::*;
+        match (self, other) {
+            (Text(a), Text(b)) => a.partial_cmp(b),
+            (Int(a), Int(b)) => a.partial_cmp(b),
+            (Float(a), Float(b)) => a.partial_cmp(b),
+            (Int(a), Float(b)) => (*a as f64).partial_cmp(b),
+            (Float(a), Int(b)) => a.partial_cmp(&(*b as f64)),
+            (Bool(a), Bool(b)) => a.partial_cmp(b),
+            (Uuid(a), Uuid(b)) => a.partial_cmp(b),
+            (Timestamp(a), Timestamp(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for MetadataValue {
+    fn from(value: &str) -> Self {
+        MetadataValue::Text(value.to_string())
+    }
+}
+
+impl From<String> for MetadataValue {
+    fn from(value: String) -> Self {
+        MetadataValue::Text(value)
+    }
+}
+
+impl From<i64> for MetadataValue {
+    fn from(value: i64) -> Self {
+        MetadataValue::Int(value)
+    }
+}
+
+impl From<f64> for MetadataValue {
+    fn from(value: f64) -> Self {
+        MetadataValue::Float(value)
+    }
+}
+
+impl From<bool> for MetadataValue {
+    fn from(value: bool) -> Self {
+        MetadataValue::Bool(value)
+    }
+}
+
+impl From<Uuid> for MetadataValue {
+    fn from(value: Uuid) -> Self {
+        MetadataValue::Uuid(value)
+    }
+}
+
+impl From<SystemTime> for MetadataValue {
+    fn from(value: SystemTime) -> Self {
+        MetadataValue::Timestamp(value)
+    }
+}
+
+// Условие на метаданные чанка, которое поисковик применяет до отбора top_k
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    Eq(String, MetadataValue),
+    In(String, Vec<MetadataValue>),
+    // Границы включительные, отсутствующая граница не ограничивает
+    Range {
+        field: String,
+        gte: Option<MetadataValue>,
+        lte: Option<MetadataValue>,
+    },
+    And(Vec<Filter>),
+}
+
+impl Filter {
+    pub fn eq(field: &str, value: impl Into<MetadataValue>) -> Self {
+        Filter::Eq(field.to_string(), value.into())
+    }
+
+    pub fn one_of<V: Into<MetadataValue>>(
+        field: &str,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        Filter::In(
+            field.to_string(),
+            values.into_iter().map(Into::into).collect(),
+        )
+    }
+
+    pub fn range(field: &str, gte: Option<MetadataValue>, lte: Option<MetadataValue>) -> Self {
+        Filter::Range {
+            field: field.to_string(),
+            gte,
+            lte,
+        }
+    }
+
+    pub fn document(doc_id: Uuid) -> Self {
+        Filter::eq(DOC_ID_FIELD, doc_id)
+    }
+
+    pub fn updated_after(since: SystemTime) -> Self {
+        Filter::range(UPDATED_AT_FIELD, Some(since.into()), None)
+    }
+
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        match self {
+            Filter::Eq(field, value) => metadata.get(field) == Some(value),
+            Filter::In(field, values) => metadata
+                .get(field)
+                .is_some_and(|value| values.contains(value)),
+            Filter::Range { field, gte, lte } => {
+                let Some(value) = metadata.get(field) else {
+                    return false;
+                };
+                let above = gte.as_ref().is_none_or(|gte| value >= gte);
+                let below = lte.as_ref().is_none_or(|lte| value <= lte);
+                above && below
+            }
+            Filter::And(filters) => filters.iter().all(|f| f.matches(metadata)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_filter_matches() {
+        let doc_id = Uuid::new_v4();
+        let updated_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let metadata = Metadata::from([
+            (DOC_ID_FIELD.to_string(), doc_id.into()),
+            (UPDATED_AT_FIELD.to_string(), updated_at.into()),
+            ("tenant".to_string(), "acme".into()),
+            ("priority".to_string(), 3i64.into()),
+        ]);
+
+        assert!(Filter::document(doc_id).matches(&metadata));
+        assert!(!Filter::document(Uuid::new_v4()).matches(&metadata));
+        assert!(Filter::one_of("tenant", ["acme", "globex"]).matches(&metadata));
+        assert!(!Filter::one_of("tag", ["faq"]).matches(&metadata));
+        assert!(Filter::range("priority", Some(2.5.into()), Some(3i64.into())).matches(&metadata));
+        assert!(!Filter::range("priority", Some(4i64.into()), None).matches(&metadata));
+        assert!(Filter::updated_after(SystemTime::UNIX_EPOCH).matches(&metadata));
+        assert!(!Filter::updated_after(updated_at + Duration::from_secs(1)).matches(&metadata));
+        assert!(
+            !Filter::And(vec![
+                Filter::eq("tenant", "acme"),
+                Filter::eq("priority", "3")
+            ])
+            .matches(&metadata)
+        );
+    }
+}
@@ -0,0 +1,9 @@
+use std::fmt::Error;
+
+#[mockall::automock]
+#[async_trait::async_trait]
+pub trait ClaimVerifier: Send + Sync {
+    // Для каждого утверждения — оценка от 0 до 1, насколько оно следует из фрагментов;
+    // результат в порядке claims
+    async fn verify(&self, claims: &[String], passages: &[String]) -> Result<Vec<f32>, Error>;
+}
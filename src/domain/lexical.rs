@@ -0,0 +1,20 @@
+use std::fmt::Error;
+
+use uuid::Uuid;
+
+use crate::domain::{document::Chunk, embedding::ScoredChunk, filter::Filter};
+
+// Полнотекстовый индекс по тексту чанков: ловит точные идентификаторы,
+// коды ошибок и названия, которые плохо переживают векторизацию
+#[mockall::automock]
+#[async_trait::async_trait]
+pub trait LexicalIndex: Send + Sync {
+    async fn save(&self, chunk: &Chunk) -> Result<(), Error>;
+    async fn delete(&self, chunk_id: Uuid) -> Result<(), Error>;
+    async fn search_text<'a>(
+        &self,
+        text: &str,
+        top_k: usize,
+        filter: Option<&'a Filter>,
+    ) -> Result<Vec<ScoredChunk>, Error>;
+}
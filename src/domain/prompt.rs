@@ -0,0 +1,181 @@
+use std::fmt::Error;
+
+use crate::domain::unswer::ContextEntry;
+
+// Переменные, доступные в шаблоне как {{имя}}
+pub const QUESTION_VAR: &str = "question";
+// Фрагменты контекста, пронумерованные с 1: "[1] текст"
+pub const CONTEXT_VAR: &str = "context";
+// Названия документов фрагментов с теми же номерами
+pub const TITLES_VAR: &str = "titles";
+// Предыдущие вопросы и ответы разговора
+pub const HISTORY_VAR: &str = "history";
+
+const VARS: [&str; 4] = [QUESTION_VAR, CONTEXT_VAR, TITLES_VAR, HISTORY_VAR];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryTurn {
+    pub question: String,
+    pub unswer: String,
+}
+
+// Шаблон промпта; id вида "name@version" однозначно определяет текст шаблона
+#[derive(Clone, Debug, PartialEq)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub version: u32,
+    pub system: String,
+    pub user: String,
+}
+
+// Готовый к отправке промпт
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prompt {
+    pub template_id: String,
+    pub system: String,
+    pub user: String,
+}
+
+fn placeholders(text: &str) -> impl Iterator<Item = &str> {
+    text.split("{{")
+        .skip(1)
+        .filter_map(|rest| rest.split_once("}}").map(|(name, _)| name.trim()))
+}
+
+// Подстановка за один проход: текст вопроса или фрагментов с {{...}} не раскрывается повторно
+fn fill(text: &str, vars: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find("{{") {
+        filled.push_str(&rest[..open]);
+        let after = &rest[open + 2..];
+        let Some(close) = after.find("}}") else {
+            rest = &rest[open..];
+            break;
+        };
+        let name = after[..close].trim();
+        match vars.iter().find(|(var, _)| *var == name) {
+            Some((_, value)) => filled.push_str(value),
+            None => filled.push_str(&rest[open..open + close + 4]),
+        }
+        rest = &after[close + 2..];
+    }
+    filled.push_str(rest);
+    filled
+}
+
+impl PromptTemplate {
+    // Шаблон с неизвестной переменной отвергается сразу, а не при первом ответе
+    pub fn new(name: &str, version: u32, system: &str, user: &str) -> Result<Self, Error> {
+        let unknown = placeholders(system)
+            .chain(placeholders(user))
+            .any(|var| !VARS.contains(&var));
+        if unknown {
+            return Err(Error);
+        }
+        Ok(Self {
+            name: name.to_string(),
+            version,
+            system: system.to_string(),
+            user: user.to_string(),
+        })
+    }
+
+    pub fn id(&self) -> String {
+        format!("{}@{}", self.name, self.version)
+    }
+
+    pub fn render(
+        &self,
+        question: &str,
+        context: &[ContextEntry],
+        history: &[HistoryTurn],
+    ) -> Prompt {
+        let passages = context
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| format!("[{}] {}", i + 1, entry.text))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let titles = context
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| format!("[{}] {}", i + 1, entry.title.as_deref().unwrap_or("—")))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let history = history
+            .iter()
+            .map(|turn| format!("Вопрос: {}\nОтвет: {}", turn.question, turn.unswer))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let vars = [
+            (QUESTION_VAR, question),
+            (CONTEXT_VAR, passages.as_str()),
+            (TITLES_VAR, titles.as_str()),
+            (HISTORY_VAR, history.as_str()),
+        ];
+        Prompt {
+            template_id: self.id(),
+            system: fill(&self.system, &vars),
+            user: fill(&self.user, &vars),
+        }
+    }
+}
+
+#[mockall::automock]
+#[async_trait::async_trait]
+pub trait PromptTemplateRepo: Send + Sync {
+    // id вида "name@version"
+    async fn read(&self, template_id: &str) -> Result<PromptTemplate, Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_render_fills_variables() {
+        let template = PromptTemplate::new(
+            "answer",
+            3,
+            "Отвечай кратко.",
+            "{{history}}\n\nИсточники:\n{{titles}}\n\n{{context}}\n\nВопрос: {{question}}",
+        )
+        .unwrap();
+        let context = vec![
+            ContextEntry {
+                chunk_id: Uuid::new_v4(),
+                doc_id: Uuid::new_v4(),
+                title: Some("Книга Rust".into()),
+                text: "Rust — язык.".into(),
+                score: 0.9,
+                merged_chunks_id: Vec::new(),
+            },
+            ContextEntry {
+                chunk_id: Uuid::new_v4(),
+                doc_id: Uuid::new_v4(),
+                title: None,
+                text: "Он быстрый.".into(),
+                score: 0.8,
+                merged_chunks_id: Vec::new(),
+            },
+        ];
+        let history = vec![HistoryTurn {
+            question: "Привет".into(),
+            unswer: "Здравствуйте".into(),
+        }];
+
+        let prompt = template.render("Что такое {{context}}?", &context, &history);
+
+        assert_eq!(prompt.template_id, "answer@3");
+        assert_eq!(prompt.system, "Отвечай кратко.");
+        assert_eq!(
+            prompt.user,
+            "Вопрос: Привет\nОтвет: Здравствуйте\n\nИсточники:\n[1] Книга Rust\n[2] —\n\n\
+             [1] Rust — язык.\n\n[2] Он быстрый.\n\nВопрос: Что такое {{context}}?"
+        );
+        assert!(PromptTemplate::new("answer", 1, "", "{{answer_style}}").is_err());
+    }
+}
@@ -2,18 +2,46 @@ use std::fmt::Error;
 
 use uuid::Uuid;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RewriteKind {
+    // Самостоятельная формулировка вопроса без отсылок к разговору
+    Standalone,
+    Paraphrase,
+    // Гипотетический ответ (HyDE): ищется по близости к ответу, а не к вопросу
+    Hypothetical,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryRewrite {
+    pub kind: RewriteKind,
+    pub text: String,
+}
+
 pub struct Question {
     pub id: Uuid,
     pub text: String,
+    // Переформулировки, по которым поиск идёт вместе с исходным текстом
+    pub rewrites: Vec<QueryRewrite>,
+    pub conversation_id: Option<Uuid>,
+    // Уточняющий вопрос разговора, переписанный без отсылок к предыдущим ходам
+    pub standalone: Option<String>,
 }
 
 impl Question {
     pub fn new(text: String) -> Self {
         Self {
             id: Uuid::new_v4(),
-            text: text,
+            text,
+            rewrites: Vec::new(),
+            conversation_id: None,
+            standalone: None,
         }
     }
+
+    // Текст, по которому ищется контекст: самостоятельная формулировка, если она есть
+    pub fn search_text(&self) -> &str {
+        self.standalone.as_deref().unwrap_or(&self.text)
+    }
 }
 
 #[mockall::automock]
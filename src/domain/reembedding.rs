@@ -0,0 +1,29 @@
+use std::fmt::Error;
+
+use uuid::Uuid;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReembeddingCheckpoint {
+    pub model_id: String,
+    pub last_chunk_id: Option<Uuid>,
+    pub processed: usize,
+    pub completed: bool,
+}
+
+impl ReembeddingCheckpoint {
+    pub fn new(model_id: String) -> Self {
+        Self {
+            model_id,
+            last_chunk_id: None,
+            processed: 0,
+            completed: false,
+        }
+    }
+}
+
+#[mockall::automock]
+#[async_trait::async_trait]
+pub trait ReembeddingCheckpointRepo: Send + Sync {
+    async fn save(&self, checkpoint: &ReembeddingCheckpoint) -> Result<(), Error>;
+    async fn read(&self, model_id: &str) -> Result<Option<ReembeddingCheckpoint>, Error>;
+}
@@ -0,0 +1,24 @@
+use std::fmt::Error;
+
+use uuid::Uuid;
+
+use crate::domain::embedding::ScoredChunk;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RerankCandidate {
+    pub chunk_id: Uuid,
+    pub text: String,
+    // Оценка этапа поиска
+    pub score: f32,
+}
+
+#[mockall::automock]
+#[async_trait::async_trait]
+pub trait Reranker: Send + Sync {
+    // Заново оценивает кандидатов относительно вопроса, результат по убыванию оценки
+    async fn rerank(
+        &self,
+        question: &str,
+        candidates: Vec<RerankCandidate>,
+    ) -> Result<Vec<ScoredChunk>, Error>;
+}
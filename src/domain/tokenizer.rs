@@ -0,0 +1,7 @@
+// Подсчёт токенов в терминах той модели, которой отправляется промпт
+#[mockall::automock]
+pub trait Tokenizer: Send + Sync {
+    fn count(&self, text: &str) -> usize;
+    // Начало текста, укладывающееся в max_tokens
+    fn truncate(&self, text: &str, max_tokens: usize) -> String;
+}
@@ -1,38 +1,267 @@
 use std::fmt::Error;
+use std::ops::Add;
+use std::time::{Duration, SystemTime};
 
+use futures::stream::{self, BoxStream};
 use uuid::Uuid;
 
+use crate::domain::document::Chunk;
+use crate::domain::embedding::Metric;
+use crate::domain::filter::{MetadataValue, TITLE_FIELD};
+use crate::domain::prompt::{HistoryTurn, Prompt};
+
+// Ответ модели по частям, в порядке генерации
+pub type TokenStream = BoxStream<'static, Result<String, Error>>;
+
+// Документ, близкий к вопросу, но недостаточно для ответа
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct SuggestedDocument {
+    pub doc_id: Uuid,
+    pub title: Option<String>,
+    pub score: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UnswerEvent {
+    // Очередной кусок текста от модели
+    Delta(String),
+    // Модель закончила, ответ сохранён. Текст может отличаться от склейки Delta:
+    // ссылки на несуществующие фрагменты из него удалены
+    Done {
+        unswer_id: Uuid,
+        text: String,
+        citations: Vec<Citation>,
+        // Проверяется после генерации: перегенерировать или скрыть уже отданный текст нельзя
+        groundedness: Option<f32>,
+    },
+    // Единственное событие потока, если ответа в корпусе нет
+    NotFound {
+        suggestions: Vec<SuggestedDocument>,
+    },
+}
+
+pub type UnswerStream = BoxStream<'static, Result<UnswerEvent, Error>>;
+
+// Фрагмент контекста ответа; порядок записей совпадает с порядком ранжирования
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContextEntry {
+    pub chunk_id: Uuid,
+    pub doc_id: Uuid,
+    // Название документа из метаданных, если оно задано
+    pub title: Option<String>,
+    pub text: String,
+    pub score: f32,
+    // Другие найденные чанки, склеенные в этот фрагмент
+    pub merged_chunks_id: Vec<Uuid>,
+}
+
+// В какой шкале context_scores
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScoreKind {
+    // Векторная близость по метрике поисковика
+    Similarity(Metric),
+    // Оценка после слияния векторного и лексического поиска
+    Fused,
+    // Оценка реранкера
+    Reranked,
+}
+
+impl ContextEntry {
+    pub fn from_chunk(chunk: Chunk, score: f32) -> Self {
+        let title = match chunk.metadata.get(TITLE_FIELD) {
+            Some(MetadataValue::Text(title)) => Some(title.clone()),
+            _ => None,
+        };
+        Self {
+            chunk_id: chunk.id,
+            doc_id: chunk.doc_id,
+            title,
+            text: chunk.text,
+            score,
+            merged_chunks_id: Vec::new(),
+        }
+    }
+}
+
+// Ссылка [number] в тексте ответа на фрагмент контекста
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct Citation {
+    pub number: usize,
+    pub chunk_id: Uuid,
+    pub doc_id: Uuid,
+    // Подтверждаемый ссылкой фрагмент ответа, в символах: start..end
+    pub start: usize,
+    pub end: usize,
+}
+
+// Расход токенов по отчёту модели
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+impl Add for TokenUsage {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            prompt_tokens: self.prompt_tokens + other.prompt_tokens,
+            completion_tokens: self.completion_tokens + other.completion_tokens,
+        }
+    }
+}
+
+// Ответ модели на промпт; usage — None, если модель не сообщает расход токенов
+#[derive(Clone, Debug, PartialEq)]
+pub struct Generation {
+    pub text: String,
+    pub usage: Option<TokenUsage>,
+}
+
+// Ответ модели, не сообщившей расход токенов
+impl From<String> for Generation {
+    fn from(text: String) -> Self {
+        Self { text, usage: None }
+    }
+}
+
+impl From<&str> for Generation {
+    fn from(text: &str) -> Self {
+        text.to_string().into()
+    }
+}
+
+// Сколько заняли этапы подготовки ответа
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UnswerTimings {
+    // Поиск, чтение чанков и сборка контекста
+    pub retrieval: Duration,
+    pub rerank: Duration,
+    // Все обращения к модели за текстом ответа, включая перегенерацию
+    pub generation: Duration,
+}
+
 pub struct Unswer {
     pub id: Uuid,
+    pub question_id: Uuid,
+    pub created_at: SystemTime,
     pub text: String,
     pub context_chunks_id: Vec<Uuid>,
+    // Оценка каждого чанка контекста в шкале score_kind, в порядке context_chunks_id
+    pub context_scores: Vec<f32>,
+    pub score_kind: ScoreKind,
+    // Параметры поиска, с которыми собран контекст
+    pub similar_k: usize,
+    pub min_score: Option<f32>,
+    // Найденные чанки, не поместившиеся в окно контекста модели
+    pub dropped_chunks_id: Vec<Uuid>,
+    // Найденные чанки, склеенные с соседями или заменённые документом
+    pub merged_chunks_id: Vec<Uuid>,
+    // Последний чанк контекста, обрезанный по окну; None — все чанки целиком
+    pub truncated_chunk_id: Option<Uuid>,
+    pub citations: Vec<Citation>,
+    // Шаблон промпта "name@version"; None — промпт собран самой моделью
+    pub template_id: Option<String>,
+    // Доля утверждений ответа, подтверждённых контекстом; None — ответ не проверялся
+    pub groundedness: Option<f32>,
+    pub unsupported_claims: Vec<String>,
+    pub model_id: String,
+    // Суммарно по всем попыткам генерации
+    pub usage: Option<TokenUsage>,
+    pub timings: UnswerTimings,
+    // Ответ взят из кэша: id исходного ответа на похожий вопрос
+    pub cached_from: Option<Uuid>,
 }
 
 impl Unswer {
-    pub fn new(text: String, context: Vec<Uuid>) -> Self {
+    // i-й элемент context_chunks_id соответствует i-му фрагменту, переданному модели
+    pub fn new(
+        question_id: Uuid,
+        text: String,
+        context: &[ContextEntry],
+        score_kind: ScoreKind,
+    ) -> Self {
         Self {
             id: Uuid::new_v4(),
-            text: text,
-            context_chunks_id: context,
+            question_id,
+            created_at: SystemTime::now(),
+            text,
+            context_chunks_id: context.iter().map(|c| c.chunk_id).collect(),
+            context_scores: context.iter().map(|c| c.score).collect(),
+            score_kind,
+            similar_k: 0,
+            min_score: None,
+            dropped_chunks_id: Vec::new(),
+            merged_chunks_id: context
+                .iter()
+                .flat_map(|c| c.merged_chunks_id.iter().copied())
+                .collect(),
+            truncated_chunk_id: None,
+            citations: Vec::new(),
+            template_id: None,
+            groundedness: None,
+            unsupported_claims: Vec::new(),
+            model_id: String::new(),
+            usage: None,
+            timings: UnswerTimings::default(),
+            cached_from: None,
         }
     }
 }
 
 #[mockall::automock]
 #[async_trait::async_trait]
-pub trait UnswerRepo {
+pub trait UnswerRepo: Send + Sync {
     async fn save(&self, unswer: &Unswer) -> Result<(), Error>;
     async fn read(&self, unswer_id: Uuid) -> Result<Unswer, Error>;
     async fn delete(&self, unswer_id: Uuid) -> Result<(), Error>;
     async fn update(&self, unswer: &Unswer) -> Result<(), Error>;
+    // Все ответы на вопрос, от старых к новым
+    async fn list_by_question(&self, question_id: Uuid) -> Result<Vec<Unswer>, Error>;
 }
 
 #[mockall::automock]
 #[async_trait::async_trait]
-pub trait LLM {
+pub trait LLM: Send + Sync {
+    // Модель, которой сгенерирован ответ; сохраняется вместе с ним
+    fn model_id(&self) -> String;
+    // Фрагменты context нумеруются с 1 в переданном порядке;
+    // утверждения ответа сопровождаются ссылками на них вида [2] или [1, 3].
+    // history — предыдущие ходы разговора, от старых к новым; пуст для отдельного вопроса.
     async fn formulate_unswer(
         &self,
         question: String,
         context: Vec<String>,
-    ) -> Result<String, Error>;
+        history: Vec<HistoryTurn>,
+    ) -> Result<Generation, Error>;
+    // То же, что formulate_unswer, но текст приходит по мере генерации.
+    // Модели без потоковой генерации отдают ответ одним куском.
+    async fn stream_unswer(
+        &self,
+        question: String,
+        context: Vec<String>,
+        history: Vec<HistoryTurn>,
+    ) -> Result<TokenStream, Error> {
+        let text = self
+            .formulate_unswer(question, context, history)
+            .await?
+            .text;
+        Ok(Box::pin(stream::once(async move { Ok(text) })))
+    }
+    // Произвольный запрос к модели для служебных задач (переранжирование и т.п.)
+    async fn complete(&self, prompt: String) -> Result<String, Error>;
+    // Ответ по готовому промпту из шаблона. Модели без отдельной системной роли
+    // получают системную часть перед пользовательской.
+    async fn generate(&self, prompt: Prompt) -> Result<Generation, Error> {
+        let text = self
+            .complete(format!("{}\n\n{}", prompt.system, prompt.user))
+            .await?;
+        Ok(Generation { text, usage: None })
+    }
+    // То же, что generate, но текст приходит по мере генерации
+    async fn stream_generate(&self, prompt: Prompt) -> Result<TokenStream, Error> {
+        let text = self.generate(prompt).await?.text;
+        Ok(Box::pin(stream::once(async move { Ok(text) })))
+    }
 }
@@ -1,8 +1,11 @@
+pub mod adapter;
 pub mod domain;
 pub mod service;
+#[cfg(test)]
+mod testing;
 
 fn main() {
-    let text = "
+    let _text = "
         Мороз и солнце; день чудесный!
         Еще ты дремлешь, друг прелестный —
         Пора, красавица, проснись:
@@ -1,4 +1,17 @@
 pub mod document;
 pub use document::DocumentService;
+pub mod ask;
+pub mod cache;
+pub mod citation;
+pub mod context;
+pub mod feedback;
+pub mod groundedness;
+pub mod mmr;
+pub mod neighbours;
+pub mod parent;
+pub mod query;
 pub mod question;
+pub mod reembedding;
+pub mod rerank;
+pub mod retrieval;
 pub mod unswer;
@@ -0,0 +1,142 @@
+use std::fmt::Error;
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::domain::filter::Filter;
+use crate::service::question::QuestionService;
+use crate::service::unswer::{UnswerRequest, UnswerResult, UnswerService};
+
+pub struct AskRequest {
+    pub text: String,
+    // Вопрос продолжает разговор; None — отдельный вопрос
+    pub conversation_id: Option<Uuid>,
+    pub similar_k: usize,
+    pub min_score: Option<f32>,
+    pub filter: Option<Filter>,
+    pub template: Option<String>,
+}
+
+impl AskRequest {
+    pub fn new(text: &str, similar_k: usize) -> Self {
+        Self {
+            text: text.to_string(),
+            conversation_id: None,
+            similar_k,
+            min_score: None,
+            filter: None,
+            template: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AskResponse {
+    pub question_id: Uuid,
+    pub result: UnswerResult,
+}
+
+// Вопрос и ответ одним вызовом. Для асинхронных конвейеров по-прежнему можно
+// сохранить вопрос через QuestionService, а ответ запросить позже по его id.
+pub struct AskService {
+    question_service: Arc<QuestionService>,
+    unswer_service: Arc<UnswerService>,
+}
+
+impl AskService {
+    pub fn new(question_service: Arc<QuestionService>, unswer_service: Arc<UnswerService>) -> Self {
+        Self {
+            question_service,
+            unswer_service,
+        }
+    }
+
+    pub async fn ask(&self, request: AskRequest) -> Result<AskResponse, Error> {
+        let question_id = match request.conversation_id {
+            Some(conversation_id) => {
+                self.question_service
+                    .process_follow_up(conversation_id, &request.text)
+                    .await?
+            }
+            None => {
+                self.question_service
+                    .process_new_question(&request.text)
+                    .await?
+            }
+        };
+
+        let mut unswer_request = UnswerRequest::new(question_id, request.similar_k);
+        unswer_request.min_score = request.min_score;
+        unswer_request.filter = request.filter;
+        unswer_request.template = request.template;
+        let result = self.unswer_service.get_unswer(&unswer_request).await?;
+
+        Ok(AskResponse {
+            question_id,
+            result,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use crate::domain::document::Chunk;
+    use crate::domain::embedding::MockTextVectorizer;
+    use crate::domain::question::Question;
+    use crate::testing::UnswerMocks;
+
+    #[tokio::test]
+    async fn test_ask_returns_question_and_answer() {
+        let chunk = Chunk::new(Uuid::new_v4(), "Rust — язык программирования.".into());
+        let chunk_id = chunk.id;
+        let mut mocks = UnswerMocks::new("Что такое Rust?", vec![(chunk, 0.9)], "Это язык [1].");
+
+        // Сохранённый вопрос и его эмбеддинг читаются сервисом ответов
+        let saved: Arc<Mutex<Option<(Uuid, String)>>> = Arc::default();
+        let saved_clone = saved.clone();
+        mocks.question_repo.expect_save().returning(move |q| {
+            *saved_clone.lock().unwrap() = Some((q.id, q.text.clone()));
+            Ok(())
+        });
+        let saved_clone = saved.clone();
+        mocks.question_repo.expect_read().returning(move |_| {
+            let (id, text) = saved_clone.lock().unwrap().clone().unwrap();
+            let mut question = Question::new(text);
+            question.id = id;
+            Ok(question)
+        });
+        mocks.embedding_repo.expect_save().returning(|_| Ok(()));
+
+        let mut mock_vectorizer = MockTextVectorizer::new();
+        mock_vectorizer
+            .expect_model_id()
+            .return_const("test-model".to_string());
+        mock_vectorizer
+            .expect_vectorize()
+            .returning(|_| Ok(vec![0.1, 0.2]));
+
+        let (unswer_service, question_repo, embedding_repo) = mocks.service_with_question_repos();
+        let question_service =
+            QuestionService::new(question_repo, embedding_repo, Arc::new(mock_vectorizer));
+        let service = AskService::new(Arc::new(question_service), Arc::new(unswer_service));
+
+        let response = service
+            .ask(AskRequest::new("Что такое Rust?", 1))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Some(response.question_id),
+            saved.lock().unwrap().as_ref().map(|s| s.0)
+        );
+        let UnswerResult::Answered(answer) = response.result else {
+            panic!("ожидался ответ");
+        };
+        assert_eq!(answer.text, "Это язык [1].");
+        assert_eq!(answer.sources[0].chunk_id, chunk_id);
+        assert_eq!(answer.citations[0].number, 1);
+    }
+}
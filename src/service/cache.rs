@@ -0,0 +1,454 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Error;
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::domain::document::{Chunk, ChunkRepo, DocumentRepo};
+use crate::domain::embedding::{QuestionEmbeddingRepo, QuestionEmbending};
+use crate::domain::unswer::{ContextEntry, Unswer, UnswerRepo};
+
+// Ответ на похожий вопрос и его контекст в исходном порядке
+pub struct CachedUnswer {
+    pub unswer: Unswer,
+    pub context: Vec<ContextEntry>,
+}
+
+impl CachedUnswer {
+    // Копия ответа для нового вопроса; модель не вызывалась, поэтому без расхода и таймингов
+    pub fn copy_for(&self, question_id: Uuid) -> Unswer {
+        let source = &self.unswer;
+        let mut unswer = Unswer::new(
+            question_id,
+            source.text.clone(),
+            &self.context,
+            source.score_kind,
+        );
+        unswer.similar_k = source.similar_k;
+        unswer.min_score = source.min_score;
+        unswer.citations = source.citations.clone();
+        unswer.template_id = source.template_id.clone();
+        unswer.groundedness = source.groundedness;
+        unswer.unsupported_claims = source.unsupported_claims.clone();
+        unswer.model_id = source.model_id.clone();
+        unswer.cached_from = Some(source.id);
+        unswer
+    }
+}
+
+// Кэш ответов: вопрос, близкий к уже отвеченному, получает сохранённый ответ
+// без поиска и генерации. Ответ годен, пока все его чанки существуют и их документы
+// не обновлялись после ответа. DocumentService::update_document пересоздаёт чанки
+// и сдвигает updated_at, поэтому обновление документа сбрасывает ответы по нему.
+pub struct UnswerCache {
+    embedding_repo: Arc<dyn QuestionEmbeddingRepo>,
+    unswer_repo: Arc<dyn UnswerRepo>,
+    chunk_repo: Arc<dyn ChunkRepo>,
+    document_repo: Arc<dyn DocumentRepo>,
+    min_similarity: f32,
+    // Сколько похожих вопросов проверять, прежде чем признать промах
+    candidates: usize,
+}
+
+impl UnswerCache {
+    pub fn new(
+        embedding_repo: Arc<dyn QuestionEmbeddingRepo>,
+        unswer_repo: Arc<dyn UnswerRepo>,
+        chunk_repo: Arc<dyn ChunkRepo>,
+        document_repo: Arc<dyn DocumentRepo>,
+        min_similarity: f32,
+    ) -> Self {
+        Self {
+            embedding_repo,
+            unswer_repo,
+            chunk_repo,
+            document_repo,
+            min_similarity,
+            candidates: 3,
+        }
+    }
+
+    pub fn with_candidates(mut self, candidates: usize) -> Self {
+        self.candidates = candidates;
+        self
+    }
+
+    // Самый свежий годный ответ на похожий вопрос, полученный с тем же шаблоном промпта
+    // и теми же similar_k и min_score: с другими параметрами контекст был бы другим
+    pub async fn lookup(
+        &self,
+        embedding: &QuestionEmbending,
+        template_id: Option<&str>,
+        similar_k: usize,
+        min_score: Option<f32>,
+    ) -> Result<Option<CachedUnswer>, Error> {
+        // Эмбеддинг самого вопроса уже сохранён и найдётся первым
+        let similar = self
+            .embedding_repo
+            .search_similar(&embedding.vec, &embedding.model_id, self.candidates + 1)
+            .await?;
+
+        let mut checked = HashSet::new();
+        for scored in similar
+            .into_iter()
+            .filter(|s| s.question_id != embedding.question_id && s.score >= self.min_similarity)
+            .take(self.candidates)
+        {
+            let latest = self
+                .unswer_repo
+                .list_by_question(scored.question_id)
+                .await?
+                .into_iter()
+                .filter(|unswer| {
+                    unswer.template_id.as_deref() == template_id
+                        && unswer.similar_k == similar_k
+                        && unswer.min_score == min_score
+                })
+                .max_by_key(|unswer| unswer.created_at);
+            let Some(unswer) = latest else {
+                continue;
+            };
+            // Копия новее исходного ответа, и по её created_at обновление документа
+            // между ними не заметить, поэтому проверяется и отдаётся исходный ответ.
+            // Повторы одного вопроса ведут к одному источнику, он проверяется один раз.
+            let source_id = unswer.cached_from.unwrap_or(unswer.id);
+            if !checked.insert(source_id) {
+                continue;
+            }
+            let unswer = match unswer.cached_from {
+                Some(source_id) => match self.unswer_repo.read(source_id).await {
+                    Ok(source) => source,
+                    // Исходный ответ удалён, копии его больше не переиспользуются
+                    Err(_) => continue,
+                },
+                None => unswer,
+            };
+            if let Some(context) = self.validate(&unswer).await? {
+                return Ok(Some(CachedUnswer { unswer, context }));
+            }
+        }
+        Ok(None)
+    }
+
+    // None — ответ устарел: чанк удалён или документ обновлён после ответа
+    async fn validate(&self, unswer: &Unswer) -> Result<Option<Vec<ContextEntry>>, Error> {
+        let mut chunks: HashMap<Uuid, Chunk> = self
+            .chunk_repo
+            .read_many(&unswer.context_chunks_id)
+            .await?
+            .into_iter()
+            .map(|chunk| (chunk.id, chunk))
+            .collect();
+
+        let mut context = Vec::with_capacity(unswer.context_chunks_id.len());
+        for (chunk_id, score) in unswer.context_chunks_id.iter().zip(&unswer.context_scores) {
+            let Some(chunk) = chunks.remove(chunk_id) else {
+                return Ok(None);
+            };
+            context.push(ContextEntry::from_chunk(chunk, *score));
+        }
+
+        let doc_ids: HashSet<Uuid> = context.iter().map(|entry| entry.doc_id).collect();
+        for doc_id in doc_ids {
+            // Удалённый документ тоже делает ответ устаревшим
+            match self.document_repo.read(doc_id).await {
+                Ok(document) if document.updated_at <= unswer.created_at => {}
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    use crate::domain::document::{Document, MockChunkRepo, MockDocumentRepo};
+    use crate::domain::embedding::{Metric, MockQuestionEmbeddingRepo, ScoredQuestion};
+    use crate::domain::unswer::{MockUnswerRepo, ScoreKind};
+
+    fn embedding(question_id: Uuid) -> QuestionEmbending {
+        QuestionEmbending {
+            id: Uuid::new_v4(),
+            question_id,
+            rewrite: None,
+            model_id: "test-model".into(),
+            vec: vec![1.0],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lookup_skips_answers_over_updated_documents() {
+        let question_id = Uuid::new_v4();
+        let (stale_question, fresh_question) = (Uuid::new_v4(), Uuid::new_v4());
+        let (stale_doc, fresh_doc) = (
+            Document::new("Старый".into()),
+            Document::new("Новый".into()),
+        );
+        let stale_chunk = Chunk::new(stale_doc.id, "Rust 1.0".into());
+        let fresh_chunk = Chunk::new(fresh_doc.id, "Rust 1.81".into());
+
+        let unswer_at = |chunk: &Chunk, created_at: SystemTime| {
+            let context = [ContextEntry::from_chunk(chunk.clone(), 0.9)];
+            let mut unswer = Unswer::new(
+                Uuid::new_v4(),
+                "Ответ [1].".into(),
+                &context,
+                ScoreKind::Similarity(Metric::Cosine),
+            );
+            unswer.created_at = created_at;
+            unswer.similar_k = 1;
+            unswer
+        };
+        // Документ ближайшего вопроса обновлён после ответа на него
+        let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        let stale = unswer_at(&stale_chunk, hour_ago);
+        let fresh = unswer_at(&fresh_chunk, SystemTime::now() + Duration::from_secs(1));
+        let fresh_id = fresh.id;
+
+        let mut mock_embedding_repo = MockQuestionEmbeddingRepo::new();
+        mock_embedding_repo
+            .expect_search_similar()
+            .withf(|_, model_id, _| model_id == "test-model")
+            .returning(move |_, _, _| {
+                Ok(vec![
+                    ScoredQuestion {
+                        question_id,
+                        score: 1.0,
+                    },
+                    ScoredQuestion {
+                        question_id: stale_question,
+                        score: 0.98,
+                    },
+                    ScoredQuestion {
+                        question_id: fresh_question,
+                        score: 0.96,
+                    },
+                ])
+            });
+
+        let mut mock_unswer_repo = MockUnswerRepo::new();
+        let mut answers = HashMap::from([(stale_question, stale), (fresh_question, fresh)]);
+        mock_unswer_repo
+            .expect_list_by_question()
+            .times(2)
+            .returning(move |id| Ok(answers.remove(&id).into_iter().collect()));
+
+        let chunks = [stale_chunk, fresh_chunk];
+        let mut mock_chunk_repo = MockChunkRepo::new();
+        mock_chunk_repo.expect_read_many().returning(move |ids| {
+            Ok(chunks
+                .iter()
+                .filter(|c| ids.contains(&c.id))
+                .cloned()
+                .collect())
+        });
+
+        let mut mock_document_repo = MockDocumentRepo::new();
+        mock_document_repo.expect_read().returning(move |id| {
+            Ok(if id == stale_doc.id {
+                stale_doc.clone()
+            } else {
+                fresh_doc.clone()
+            })
+        });
+
+        let cache = UnswerCache::new(
+            Arc::new(mock_embedding_repo),
+            Arc::new(mock_unswer_repo),
+            Arc::new(mock_chunk_repo),
+            Arc::new(mock_document_repo),
+            0.95,
+        );
+
+        let cached = cache
+            .lookup(&embedding(question_id), None, 1, None)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(cached.unswer.id, fresh_id);
+        assert_eq!(cached.context[0].text, "Rust 1.81");
+        let copy = cached.copy_for(question_id);
+        assert_eq!(copy.cached_from, Some(fresh_id));
+        assert_eq!(copy.question_id, question_id);
+    }
+
+    // Кэш над одним документом и одним ответом на похожий вопрос
+    fn single_answer_cache(document: Document, chunk: Chunk, unswer: Unswer) -> UnswerCache {
+        let similar_question = unswer.question_id;
+        let mut mock_embedding_repo = MockQuestionEmbeddingRepo::new();
+        mock_embedding_repo
+            .expect_search_similar()
+            .returning(move |_, _, _| {
+                Ok(vec![ScoredQuestion {
+                    question_id: similar_question,
+                    score: 0.99,
+                }])
+            });
+        let mut mock_unswer_repo = MockUnswerRepo::new();
+        mock_unswer_repo
+            .expect_list_by_question()
+            .return_once(move |_| Ok(vec![unswer]));
+        let mut mock_chunk_repo = MockChunkRepo::new();
+        mock_chunk_repo
+            .expect_read_many()
+            .returning(move |_| Ok(vec![chunk.clone()]));
+        let mut mock_document_repo = MockDocumentRepo::new();
+        mock_document_repo
+            .expect_read()
+            .returning(move |_| Ok(document.clone()));
+
+        UnswerCache::new(
+            Arc::new(mock_embedding_repo),
+            Arc::new(mock_unswer_repo),
+            Arc::new(mock_chunk_repo),
+            Arc::new(mock_document_repo),
+            0.95,
+        )
+    }
+
+    fn answer(chunk: &Chunk, similar_k: usize) -> Unswer {
+        let context = [ContextEntry::from_chunk(chunk.clone(), 0.9)];
+        let mut unswer = Unswer::new(
+            Uuid::new_v4(),
+            "Ответ [1].".into(),
+            &context,
+            ScoreKind::Similarity(Metric::Cosine),
+        );
+        unswer.similar_k = similar_k;
+        unswer
+    }
+
+    #[tokio::test]
+    async fn test_lookup_skips_answers_with_other_search_params() {
+        let document = Document::new("Rust".into());
+        let chunk = Chunk::new(document.id, "Rust".into());
+        let unswer = answer(&chunk, 5);
+        let cache = single_answer_cache(document, chunk, unswer);
+
+        let cached = cache
+            .lookup(&embedding(Uuid::new_v4()), None, 1, None)
+            .await
+            .unwrap();
+
+        assert!(cached.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_lookup_misses_after_update_that_reuses_chunk_ids() {
+        // Чанк с тем же id ещё читается, но документ обновлён после ответа
+        let mut document = Document::new("Rust 1.80".into());
+        let chunk = Chunk::new(document.id, "Rust".into());
+        let unswer = answer(&chunk, 1);
+        document.update("Rust 1.81".into());
+        document.updated_at = unswer.created_at + Duration::from_secs(1);
+        let cache = single_answer_cache(document, chunk, unswer);
+
+        let cached = cache
+            .lookup(&embedding(Uuid::new_v4()), None, 1, None)
+            .await
+            .unwrap();
+
+        assert!(cached.is_none());
+    }
+
+    // Один вопрос задан несколько раз: у первого исходный ответ, у повторов его копии
+    fn repeated_question_cache(document: Document, chunk: Chunk, source: Unswer) -> UnswerCache {
+        let repeats: Vec<Unswer> = (0..5)
+            .map(|i| {
+                let mut copy = answer(&chunk, source.similar_k);
+                copy.created_at = source.created_at + Duration::from_secs(60 * (i + 1));
+                copy.cached_from = Some(source.id);
+                copy
+            })
+            .collect();
+        // Свежие повторы ближе всего к новому вопросу
+        let mut similar: Vec<ScoredQuestion> = repeats
+            .iter()
+            .rev()
+            .map(|unswer| ScoredQuestion {
+                question_id: unswer.question_id,
+                score: 0.99,
+            })
+            .collect();
+        similar.push(ScoredQuestion {
+            question_id: source.question_id,
+            score: 0.99,
+        });
+
+        let mut mock_embedding_repo = MockQuestionEmbeddingRepo::new();
+        mock_embedding_repo
+            .expect_search_similar()
+            .returning(move |_, _, _| Ok(similar.clone()));
+        let mut answers: HashMap<Uuid, Unswer> = repeats
+            .into_iter()
+            .map(|unswer| (unswer.question_id, unswer))
+            .collect();
+        let mut mock_unswer_repo = MockUnswerRepo::new();
+        mock_unswer_repo
+            .expect_list_by_question()
+            .returning(move |id| Ok(answers.remove(&id).into_iter().collect()));
+        let source_id = source.id;
+        mock_unswer_repo
+            .expect_read()
+            .withf(move |id| *id == source_id)
+            .times(1)
+            .return_once(move |_| Ok(source));
+        let mut mock_chunk_repo = MockChunkRepo::new();
+        mock_chunk_repo
+            .expect_read_many()
+            .times(1)
+            .returning(move |_| Ok(vec![chunk.clone()]));
+        let mut mock_document_repo = MockDocumentRepo::new();
+        mock_document_repo
+            .expect_read()
+            .returning(move |_| Ok(document.clone()));
+
+        UnswerCache::new(
+            Arc::new(mock_embedding_repo),
+            Arc::new(mock_unswer_repo),
+            Arc::new(mock_chunk_repo),
+            Arc::new(mock_document_repo),
+            0.95,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_lookup_follows_copies_of_repeated_question_to_source() {
+        let document = Document::new("Rust".into());
+        let chunk = Chunk::new(document.id, "Rust".into());
+        let source = answer(&chunk, 1);
+        let source_id = source.id;
+        let cache = repeated_question_cache(document, chunk, source);
+
+        let cached = cache
+            .lookup(&embedding(Uuid::new_v4()), None, 1, None)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(cached.unswer.id, source_id);
+        assert_eq!(cached.copy_for(Uuid::new_v4()).cached_from, Some(source_id));
+    }
+
+    #[tokio::test]
+    async fn test_lookup_checks_source_of_copies_against_document_update() {
+        // Документ обновлён после исходного ответа, но до всех копий
+        let mut document = Document::new("Rust 1.80".into());
+        let chunk = Chunk::new(document.id, "Rust".into());
+        let source = answer(&chunk, 1);
+        document.update("Rust 1.81".into());
+        document.updated_at = source.created_at + Duration::from_secs(1);
+        let cache = repeated_question_cache(document, chunk, source);
+
+        let cached = cache
+            .lookup(&embedding(Uuid::new_v4()), None, 1, None)
+            .await
+            .unwrap();
+
+        assert!(cached.is_none());
+    }
+}
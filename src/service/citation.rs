@@ -0,0 +1,162 @@
+use crate::domain::unswer::{Citation, ContextEntry};
+
+// Ссылка длиннее этого числа символов считается обычным текстом в квадратных скобках
+const MAX_MARKER_LEN: usize = 16;
+
+pub(crate) fn is_sentence_end(c: char) -> bool {
+    matches!(c, '.' | '!' | '?' | '\n')
+}
+
+// Номера из ссылки вида [2] или [1, 3]; None, если в скобках не только номера
+fn marker_numbers(marker: &str) -> Option<Vec<usize>> {
+    marker
+        .split(',')
+        .map(|n| n.trim().parse::<usize>().ok())
+        .collect()
+}
+
+// Разбирает ссылки [n] в ответе модели. Номер n указывает на n-й фрагмент контекста (с 1).
+// Ссылки на несуществующие фрагменты удаляются из текста. Каждая ссылка покрывает
+// предложение, после которого стоит; смещения считаются в символах очищенного текста.
+pub fn parse_citations(answer: &str, context: &[ContextEntry]) -> (String, Vec<Citation>) {
+    let chars: Vec<char> = answer.chars().collect();
+    let mut text = String::with_capacity(answer.len());
+    let mut len = 0;
+    let mut citations = Vec::new();
+    // Границы текущего предложения без окружающих пробелов
+    let mut sentence = (0, 0);
+    let mut started = false;
+    let mut previous = (0, 0);
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let close = (c == '[')
+            .then(|| {
+                chars[i + 1..]
+                    .iter()
+                    .take(MAX_MARKER_LEN)
+                    .position(|&c| c == ']')
+            })
+            .flatten();
+        let numbers = close.and_then(|close| {
+            let marker: String = chars[i + 1..i + 1 + close].iter().collect();
+            marker_numbers(&marker)
+        });
+
+        let (Some(close), Some(numbers)) = (close, numbers) else {
+            text.push(c);
+            len += 1;
+            if !c.is_whitespace() {
+                if !started {
+                    sentence.0 = len - 1;
+                    started = true;
+                }
+                sentence.1 = len;
+            }
+            if is_sentence_end(c) {
+                if started {
+                    previous = sentence;
+                }
+                started = false;
+            }
+            i += 1;
+            continue;
+        };
+
+        let valid: Vec<usize> = numbers
+            .into_iter()
+            .filter(|n| (1..=context.len()).contains(n))
+            .collect();
+        // Ссылка после точки относится к только что закончившемуся предложению
+        let (start, end) = if started { sentence } else { previous };
+
+        for number in &valid {
+            let entry = &context[number - 1];
+            citations.push(Citation {
+                number: *number,
+                chunk_id: entry.chunk_id,
+                doc_id: entry.doc_id,
+                start,
+                end,
+            });
+        }
+
+        if valid.is_empty() {
+            // Вместе с битой ссылкой убираем пробел перед ней
+            if text.ends_with(' ') {
+                text.pop();
+                len -= 1;
+            }
+        } else {
+            let marker = valid
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let marker = format!("[{marker}]");
+            len += marker.chars().count();
+            text.push_str(&marker);
+        }
+        i += close + 2;
+    }
+
+    (text, citations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn context(n: u128) -> Vec<ContextEntry> {
+        (1..=n)
+            .map(|i| ContextEntry {
+                chunk_id: Uuid::from_u128(i),
+                doc_id: Uuid::from_u128(100 + i),
+                title: None,
+                text: format!("фрагмент {i}"),
+                score: 1.0,
+                merged_chunks_id: Vec::new(),
+            })
+            .collect()
+    }
+
+    fn span(text: &str, citation: &Citation) -> String {
+        text.chars()
+            .skip(citation.start)
+            .take(citation.end - citation.start)
+            .collect()
+    }
+
+    #[test]
+    fn test_citations_cover_their_sentences() {
+        let (text, citations) = parse_citations(
+            "Rust компилируется в машинный код [1]. Сборщика мусора нет.[2, 3] Массив [a] — не ссылка.",
+            &context(3),
+        );
+
+        assert_eq!(
+            text,
+            "Rust компилируется в машинный код [1]. Сборщика мусора нет.[2, 3] Массив [a] — не ссылка."
+        );
+        let numbers: Vec<usize> = citations.iter().map(|c| c.number).collect();
+        assert_eq!(numbers, vec![1, 2, 3]);
+        assert_eq!(
+            span(&text, &citations[0]),
+            "Rust компилируется в машинный код"
+        );
+        assert_eq!(span(&text, &citations[1]), "Сборщика мусора нет.");
+        assert_eq!(citations[2].chunk_id, Uuid::from_u128(3));
+        assert_eq!(citations[2].doc_id, Uuid::from_u128(103));
+    }
+
+    #[test]
+    fn test_citations_outside_context_are_removed() {
+        let (text, citations) = parse_citations("Ответ [7]. Ещё [1, 9].", &context(2));
+
+        assert_eq!(text, "Ответ. Ещё [1].");
+        assert_eq!(citations.len(), 1);
+        assert_eq!(span(&text, &citations[0]), "Ещё");
+    }
+}
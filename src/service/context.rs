@@ -0,0 +1,220 @@
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::domain::prompt::HistoryTurn;
+use crate::domain::tokenizer::Tokenizer;
+use crate::domain::unswer::ContextEntry;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ContextBudget {
+    // Размер окна контекста модели в токенах
+    pub context_window: usize,
+    // Сколько токенов оставить под ответ модели
+    pub answer_tokens: usize,
+    // Сколько токенов занимают инструкции промпта помимо вопроса и фрагментов
+    pub prompt_tokens: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AssembledContext {
+    pub entries: Vec<ContextEntry>,
+    // Чанки, не поместившиеся в бюджет, в порядке ранжирования
+    pub dropped: Vec<Uuid>,
+    // Чанк, от которого в контекст попало только начало
+    pub truncated: Option<Uuid>,
+}
+
+// Набирает контекст из фрагментов с наибольшим рангом, пока они помещаются в окно модели
+pub struct ContextAssembler {
+    tokenizer: Arc<dyn Tokenizer>,
+    budget: ContextBudget,
+    // Обрезать первый не поместившийся фрагмент вместо того, чтобы отбросить его целиком
+    truncate_last: bool,
+}
+
+impl ContextAssembler {
+    pub fn new(tokenizer: Arc<dyn Tokenizer>, budget: ContextBudget) -> Self {
+        Self {
+            tokenizer,
+            budget,
+            truncate_last: false,
+        }
+    }
+
+    pub fn with_truncation(mut self) -> Self {
+        self.truncate_last = true;
+        self
+    }
+
+    // Ранжирование сохраняется: после первого не поместившегося фрагмента
+    // более низкие по рангу не добавляются, даже если они короче.
+    // Фрагменты делят окно с вопросом и предыдущими ходами разговора.
+    pub fn assemble(
+        &self,
+        question: &str,
+        history: &[HistoryTurn],
+        context: Vec<ContextEntry>,
+    ) -> AssembledContext {
+        let reserved = self.budget.answer_tokens
+            + self.budget.prompt_tokens
+            + self.tokenizer.count(question)
+            + history_tokens(self.tokenizer.as_ref(), history);
+        let mut available = self.budget.context_window.saturating_sub(reserved);
+        let mut assembled = AssembledContext::default();
+
+        let mut context = context.into_iter();
+        for mut entry in context.by_ref() {
+            let tokens = self.tokenizer.count(&entry.text);
+            if tokens <= available {
+                available -= tokens;
+                assembled.entries.push(entry);
+                continue;
+            }
+
+            if self.truncate_last && available > 0 {
+                entry.text = self.tokenizer.truncate(&entry.text, available);
+                assembled.truncated = Some(entry.chunk_id);
+                assembled.entries.push(entry);
+            } else {
+                assembled.dropped.push(entry.chunk_id);
+            }
+            break;
+        }
+        assembled
+            .dropped
+            .extend(context.map(|entry| entry.chunk_id));
+        assembled
+    }
+}
+
+fn turn_tokens(tokenizer: &dyn Tokenizer, turn: &HistoryTurn) -> usize {
+    tokenizer.count(&turn.question) + tokenizer.count(&turn.unswer)
+}
+
+fn history_tokens(tokenizer: &dyn Tokenizer, history: &[HistoryTurn]) -> usize {
+    history
+        .iter()
+        .map(|turn| turn_tokens(tokenizer, turn))
+        .sum()
+}
+
+// Самые свежие ходы разговора, которые целиком помещаются в max_tokens;
+// порядок сохраняется от старых к новым
+pub fn fit_history(
+    tokenizer: &dyn Tokenizer,
+    history: Vec<HistoryTurn>,
+    max_tokens: usize,
+) -> Vec<HistoryTurn> {
+    let mut available = max_tokens;
+    let mut fitted: Vec<HistoryTurn> = history
+        .into_iter()
+        .rev()
+        .take_while(|turn| {
+            let tokens = turn_tokens(tokenizer, turn);
+            let fits = tokens <= available;
+            available = available.saturating_sub(tokens);
+            fits
+        })
+        .collect();
+    fitted.reverse();
+    fitted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapter::tokenizer::ApproxTokenizer;
+
+    fn entries(texts: &[&str]) -> Vec<ContextEntry> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| ContextEntry {
+                chunk_id: Uuid::from_u128(i as u128),
+                doc_id: Uuid::nil(),
+                title: None,
+                text: text.to_string(),
+                score: 1.0,
+                merged_chunks_id: Vec::new(),
+            })
+            .collect()
+    }
+
+    fn assembler(context_window: usize) -> ContextAssembler {
+        let tokenizer = ApproxTokenizer::new(1);
+        let budget = ContextBudget {
+            context_window,
+            answer_tokens: 5,
+            prompt_tokens: 2,
+        };
+        ContextAssembler::new(Arc::new(tokenizer), budget)
+    }
+
+    #[test]
+    fn test_highest_ranked_chunks_fit_into_budget() {
+        // 20 - 5 - 2 - 3 (вопрос) = 10 токенов на фрагменты
+        let assembled = assembler(20).assemble("abc", &[], entries(&["aaaa", "bbbbbb", "cc", "d"]));
+
+        let ids: Vec<u128> = assembled
+            .entries
+            .iter()
+            .map(|e| e.chunk_id.as_u128())
+            .collect();
+        assert_eq!(ids, vec![0, 1]);
+        assert_eq!(
+            assembled.dropped,
+            vec![Uuid::from_u128(2), Uuid::from_u128(3)]
+        );
+        assert_eq!(assembled.truncated, None);
+    }
+
+    #[test]
+    fn test_last_chunk_is_truncated() {
+        let assembled = assembler(20).with_truncation().assemble(
+            "abc",
+            &[],
+            entries(&["aaaa", "bbbbbbbb", "cc"]),
+        );
+
+        assert_eq!(assembled.entries.len(), 2);
+        assert_eq!(assembled.entries[1].text, "bbbbbb");
+        assert_eq!(assembled.truncated, Some(Uuid::from_u128(1)));
+        assert_eq!(assembled.dropped, vec![Uuid::from_u128(2)]);
+
+        // Вопрос не оставляет места под фрагменты
+        let assembled = assembler(8)
+            .with_truncation()
+            .assemble("abc", &[], entries(&["a"]));
+        assert!(assembled.entries.is_empty());
+        assert_eq!(assembled.dropped, vec![Uuid::from_u128(0)]);
+    }
+
+    #[test]
+    fn test_history_shares_budget_with_chunks() {
+        let history = [HistoryTurn {
+            question: "qq".into(),
+            unswer: "aaaa".into(),
+        }];
+
+        // 20 - 5 - 2 - 3 (вопрос) - 6 (история) = 4 токена на фрагменты
+        let assembled = assembler(20).assemble("abc", &history, entries(&["aaaa", "bb"]));
+
+        assert_eq!(assembled.entries.len(), 1);
+        assert_eq!(assembled.dropped, vec![Uuid::from_u128(1)]);
+    }
+
+    #[test]
+    fn test_history_keeps_latest_turns() {
+        let tokenizer = ApproxTokenizer::new(1);
+        let turn = |question: &str, unswer: &str| HistoryTurn {
+            question: question.into(),
+            unswer: unswer.into(),
+        };
+        let history = vec![turn("q1", "a1"), turn("q2", "long"), turn("q3", "a3")];
+
+        let fitted = fit_history(&tokenizer, history, 10);
+
+        assert_eq!(fitted, vec![turn("q2", "long"), turn("q3", "a3")]);
+    }
+}
@@ -5,6 +5,9 @@ use uuid::Uuid;
 
 use crate::domain::document::{Chunk, ChunkRepo, Document, DocumentRepo};
 use crate::domain::embedding::{ChunkEmbending, ChunkEmbendingRepo, TextVectorizer};
+use crate::domain::filter::Metadata;
+use crate::domain::lexical::LexicalIndex;
+use crate::domain::unswer::LLM;
 
 pub struct DocumentService {
     pub max_chunk_size: usize,
@@ -13,6 +16,10 @@ pub struct DocumentService {
     embending_vectorizer: Arc<dyn TextVectorizer>,
     embending_repo: Arc<dyn ChunkEmbendingRepo>,
     semaphore: Arc<tokio::sync::Semaphore>,
+    lexical_index: Option<Arc<dyn LexicalIndex>>,
+    // Модель, которая пишет краткое содержание документа для подстановки вместо
+    // длинного документа в контекст (см. parent.rs)
+    summarizer: Option<Arc<dyn LLM>>,
 }
 
 impl DocumentService {
@@ -25,14 +32,28 @@ impl DocumentService {
         semaphore: Arc<tokio::sync::Semaphore>,
     ) -> Self {
         DocumentService {
-            max_chunk_size: max_chunk_size,
-            document_repo: document_repo,
-            chunk_repo: chunk_repo,
-            embending_vectorizer: embending_vectorizer,
-            embending_repo: embending_repo,
-            semaphore: semaphore,
+            max_chunk_size,
+            document_repo,
+            chunk_repo,
+            embending_vectorizer,
+            embending_repo,
+            semaphore,
+            lexical_index: None,
+            summarizer: None,
         }
     }
+
+    // Чанки дополнительно индексируются для гибридного поиска
+    pub fn with_lexical_index(mut self, lexical_index: Arc<dyn LexicalIndex>) -> Self {
+        self.lexical_index = Some(lexical_index);
+        self
+    }
+
+    // Новые и изменённые документы сохраняются вместе с кратким содержанием
+    pub fn with_summarizer(mut self, llm: Arc<dyn LLM>) -> Self {
+        self.summarizer = Some(llm);
+        self
+    }
 }
 
 impl DocumentService {
@@ -57,14 +78,39 @@ impl DocumentService {
             chunks.push(Chunk::new(document.id, current_chunk.to_string()));
         }
 
+        // чанки наследуют метаданные документа для фильтрации при поиске
+        let metadata = document.chunk_metadata();
+        for (position, chunk) in chunks.iter_mut().enumerate() {
+            chunk.position = position;
+            chunk.metadata = metadata.clone();
+        }
+
         chunks
     }
+
+    async fn summarize(&self, document: &mut Document) -> Result<(), Error> {
+        if let Some(llm) = &self.summarizer {
+            let prompt = format!(
+                "Кратко перескажи документ в нескольких предложениях, \
+                 сохранив ключевые факты и термины.\n\n{}",
+                document.text
+            );
+            document.summary = Some(llm.complete(prompt).await?);
+        }
+        Ok(())
+    }
 }
 
 impl DocumentService {
-    pub async fn process_new_document(&self, document: &str) -> Result<(), Error> {
+    pub async fn process_new_document(
+        &self,
+        document: &str,
+        metadata: Metadata,
+    ) -> Result<(), Error> {
         // 1. Сохраняем сам документ
-        let document = Document::new(document.to_string());
+        let mut document = Document::new(document.to_string());
+        document.metadata = metadata;
+        self.summarize(&mut document).await?;
         self.document_repo.save(&document).await?;
 
         // 2. Разбиваем на чанки
@@ -78,12 +124,16 @@ impl DocumentService {
             let chunk_repo = self.chunk_repo.clone();
             let embending_repo = self.embending_repo.clone();
             let vectorizer = self.embending_vectorizer.clone();
+            let lexical_index = self.lexical_index.clone();
 
             handles.push(tokio::spawn(async move {
                 let _permit = semaphore.acquire_owned().await.unwrap();
 
                 // Сохраняем чанк
                 chunk_repo.save(&chunk).await?;
+                if let Some(lexical_index) = lexical_index {
+                    lexical_index.save(&chunk).await?;
+                }
 
                 // Генерируем эмбеддинг
                 let embending = ChunkEmbending::new(&chunk, vectorizer.as_ref()).await?;
@@ -118,11 +168,15 @@ impl DocumentService {
             let semaphore = self.semaphore.clone();
             let chunk_repo = self.chunk_repo.clone();
             let embedding_repo = self.embending_repo.clone();
+            let lexical_index = self.lexical_index.clone();
 
             delete_handles.push(tokio::spawn(async move {
                 let _permit = semaphore.acquire().await;
                 chunk_repo.delete(chunk.id).await?;
                 embedding_repo.delete(chunk.id).await?;
+                if let Some(lexical_index) = lexical_index {
+                    lexical_index.delete(chunk.id).await?;
+                }
                 Ok::<(), Error>(())
             }));
         }
@@ -130,8 +184,11 @@ impl DocumentService {
             h.await.unwrap()?;
         }
 
-        // 3. Обновляем документ
+        // 3. Обновляем документ: новая версия, дата изменения и краткое содержание
+        // видны до записи чанков, поэтому кэш ответов перестаёт их использовать
         document.update(new_document.to_string());
+        self.summarize(&mut document).await?;
+        self.document_repo.update(&document).await?;
 
         // 4. Готовим новые чанки
         let chunks = self.prepare_document(&document);
@@ -143,12 +200,16 @@ impl DocumentService {
             let chunk_repo = self.chunk_repo.clone();
             let embedding_repo = self.embending_repo.clone();
             let vectorizer = self.embending_vectorizer.clone();
+            let lexical_index = self.lexical_index.clone();
 
             vectorize_handles.push(tokio::spawn(async move {
                 let _permit = semaphore.acquire().await;
 
                 // сохраняем чанк
                 chunk_repo.save(&chunk).await?;
+                if let Some(lexical_index) = lexical_index {
+                    lexical_index.save(&chunk).await?;
+                }
 
                 // векторизация (REST)
                 let embedding = ChunkEmbending::new(&chunk, vectorizer.as_ref()).await?;
@@ -204,6 +265,7 @@ mod tests {
 
         for chunk in &chunks {
             assert_eq!(chunk.doc_id, document.id);
+            assert_eq!(chunk.metadata, document.chunk_metadata());
             assert!(!chunk.text.is_empty(), "Chunk text should not be empty");
             assert!(
                 chunk.text.len() <= 128,
@@ -211,4 +273,56 @@ mod tests {
             );
         }
     }
+
+    #[tokio::test]
+    async fn test_update_document_saves_new_version_with_summary() {
+        let document = Document::new("старый текст".into());
+        let doc_id = document.id;
+
+        let mut doc_repo = crate::domain::document::MockDocumentRepo::new();
+        doc_repo
+            .expect_read()
+            .returning(move |_| Ok(document.clone()));
+        doc_repo
+            .expect_update()
+            .withf(|doc| {
+                doc.version == 2
+                    && doc.text == "новый текст"
+                    && doc.summary.as_deref() == Some("кратко")
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let mut chunk_repo = crate::domain::document::MockChunkRepo::new();
+        chunk_repo
+            .expect_read_by_doc()
+            .returning(|_| Ok(Vec::new()));
+        chunk_repo.expect_save().returning(|_| Ok(()));
+        let mut vectorizer = crate::domain::embedding::MockTextVectorizer::new();
+        vectorizer.expect_vectorize().returning(|_| Ok(vec![1.0]));
+        vectorizer
+            .expect_model_id()
+            .return_const("test".to_string());
+        let mut emb_repo = crate::domain::embedding::MockChunkEmbendingRepo::new();
+        emb_repo.expect_save().returning(|_| Ok(()));
+        let mut llm = crate::domain::unswer::MockLLM::new();
+        llm.expect_complete()
+            .withf(|prompt| prompt.ends_with("новый текст"))
+            .returning(|_| Ok("кратко".into()));
+
+        let service = DocumentService::new(
+            128,
+            Arc::new(doc_repo),
+            Arc::new(chunk_repo),
+            Arc::new(vectorizer),
+            Arc::new(emb_repo),
+            Arc::new(tokio::sync::Semaphore::new(5)),
+        )
+        .with_summarizer(Arc::new(llm));
+
+        service
+            .update_document(doc_id, "новый текст")
+            .await
+            .unwrap();
+    }
 }
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::fmt::Error;
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::domain::feedback::{Feedback, FeedbackRepo, Rating};
+use crate::domain::unswer::UnswerRepo;
+
+pub struct FeedbackRequest {
+    pub unswer_id: Uuid,
+    pub rating: Rating,
+    pub correction: Option<String>,
+}
+
+// Отзывы о документе: сколько оценённых ответов на него ссылались
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct DocumentFeedback {
+    pub doc_id: Uuid,
+    pub up: usize,
+    pub down: usize,
+}
+
+// Отзывы о чанке: сколько оценённых ответов получили его в контексте
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct ChunkFeedback {
+    pub chunk_id: Uuid,
+    pub up: usize,
+    pub down: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize)]
+pub struct FeedbackReport {
+    pub up: usize,
+    pub down: usize,
+    pub corrections: usize,
+    // Чаще всего цитируемые в отрицательно оценённых ответах, по убыванию down
+    pub documents: Vec<DocumentFeedback>,
+    // Чаще всего попадающие в контекст отрицательно оценённых ответов, по убыванию down
+    pub chunks: Vec<ChunkFeedback>,
+}
+
+pub struct FeedbackService {
+    pub batch_size: usize,
+    feedback_repo: Arc<dyn FeedbackRepo>,
+    unswer_repo: Arc<dyn UnswerRepo>,
+}
+
+impl FeedbackService {
+    pub fn new(
+        batch_size: usize,
+        feedback_repo: Arc<dyn FeedbackRepo>,
+        unswer_repo: Arc<dyn UnswerRepo>,
+    ) -> Self {
+        Self {
+            batch_size,
+            feedback_repo,
+            unswer_repo,
+        }
+    }
+}
+
+// Счётчики (up, down) по id; в отчёт попадают top_n с хотя бы одной отрицательной оценкой
+fn ranked(counts: HashMap<Uuid, (usize, usize)>, top_n: usize) -> Vec<(Uuid, usize, usize)> {
+    let mut ranked: Vec<(Uuid, usize, usize)> = counts
+        .into_iter()
+        .filter(|(_, (_, down))| *down > 0)
+        .map(|(id, (up, down))| (id, up, down))
+        .collect();
+    // При равном числе отрицательных выше тот, кого реже оценивали положительно
+    ranked.sort_by(|a, b| b.2.cmp(&a.2).then(a.1.cmp(&b.1)).then(a.0.cmp(&b.0)));
+    ranked.truncate(top_n);
+    ranked
+}
+
+fn count(counts: &mut HashMap<Uuid, (usize, usize)>, id: Uuid, rating: Rating) {
+    let (up, down) = counts.entry(id).or_default();
+    match rating {
+        Rating::Up => *up += 1,
+        Rating::Down => *down += 1,
+    }
+}
+
+impl FeedbackService {
+    // Отзыв привязывается к ответу, его вопросу и контексту; ответ должен существовать
+    pub async fn submit(&self, request: FeedbackRequest) -> Result<Uuid, Error> {
+        let unswer = self.unswer_repo.read(request.unswer_id).await?;
+
+        // Пустое исправление равносильно его отсутствию
+        let correction = request
+            .correction
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty());
+        let feedback = Feedback::new(&unswer, request.rating, correction);
+        self.feedback_repo.save(&feedback).await?;
+        Ok(feedback.id)
+    }
+
+    pub async fn list(&self, unswer_id: Uuid) -> Result<Vec<Feedback>, Error> {
+        self.feedback_repo.list_by_unswer(unswer_id).await
+    }
+
+    // Сводка по всем отзывам; списки документов и чанков ограничены top_n
+    pub async fn report(&self, top_n: usize) -> Result<FeedbackReport, Error> {
+        let mut report = FeedbackReport::default();
+        let mut documents = HashMap::new();
+        let mut chunks = HashMap::new();
+
+        let mut after = None;
+        loop {
+            let page = self.feedback_repo.list(after, self.batch_size).await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            after = Some(last.id);
+
+            for feedback in page {
+                match feedback.rating {
+                    Rating::Up => report.up += 1,
+                    Rating::Down => report.down += 1,
+                }
+                if feedback.correction.is_some() {
+                    report.corrections += 1;
+                }
+                for doc_id in feedback.cited_doc_ids {
+                    count(&mut documents, doc_id, feedback.rating);
+                }
+                for chunk_id in feedback.context_chunks_id {
+                    count(&mut chunks, chunk_id, feedback.rating);
+                }
+            }
+        }
+
+        report.documents = ranked(documents, top_n)
+            .into_iter()
+            .map(|(doc_id, up, down)| DocumentFeedback { doc_id, up, down })
+            .collect();
+        report.chunks = ranked(chunks, top_n)
+            .into_iter()
+            .map(|(chunk_id, up, down)| ChunkFeedback { chunk_id, up, down })
+            .collect();
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use crate::domain::embedding::Metric;
+    use crate::domain::feedback::MockFeedbackRepo;
+    use crate::domain::unswer::{Citation, ContextEntry, MockUnswerRepo, ScoreKind, Unswer};
+
+    fn unswer(cited: &[Uuid]) -> Unswer {
+        let context: Vec<ContextEntry> = cited
+            .iter()
+            .map(|&doc_id| ContextEntry {
+                chunk_id: Uuid::new_v4(),
+                doc_id,
+                title: None,
+                text: "Фрагмент".into(),
+                score: 0.9,
+                merged_chunks_id: Vec::new(),
+            })
+            .collect();
+        let mut unswer = Unswer::new(
+            Uuid::new_v4(),
+            "Ответ".into(),
+            &context,
+            ScoreKind::Similarity(Metric::Cosine),
+        );
+        unswer.citations = context
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| Citation {
+                number: i + 1,
+                chunk_id: entry.chunk_id,
+                doc_id: entry.doc_id,
+                start: 0,
+                end: 5,
+            })
+            .collect();
+        unswer
+    }
+
+    #[tokio::test]
+    async fn test_report_ranks_documents_of_negative_answers() {
+        let (doc_a, doc_b, doc_c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let rated = vec![
+            (
+                unswer(&[doc_a, doc_b]),
+                Rating::Down,
+                Some("Неверно".to_string()),
+            ),
+            (
+                unswer(&[doc_a, doc_a]),
+                Rating::Down,
+                Some("  ".to_string()),
+            ),
+            (unswer(&[doc_b]), Rating::Up, None),
+            (unswer(&[doc_c]), Rating::Down, None),
+        ];
+        let requests: Vec<FeedbackRequest> = rated
+            .iter()
+            .map(|(unswer, rating, correction)| FeedbackRequest {
+                unswer_id: unswer.id,
+                rating: *rating,
+                correction: correction.clone(),
+            })
+            .collect();
+        let mut unswers: HashMap<Uuid, Unswer> = rated
+            .into_iter()
+            .map(|(unswer, _, _)| (unswer.id, unswer))
+            .collect();
+
+        let mut mock_unswer_repo = MockUnswerRepo::new();
+        mock_unswer_repo
+            .expect_read()
+            .returning(move |id| unswers.remove(&id).ok_or(Error));
+
+        // Отзывы хранятся в памяти и отдаются страницами
+        let saved: Arc<Mutex<Vec<Feedback>>> = Arc::default();
+        let mut mock_feedback_repo = MockFeedbackRepo::new();
+        let saved_clone = saved.clone();
+        mock_feedback_repo.expect_save().returning(move |feedback| {
+            saved_clone.lock().unwrap().push(feedback.clone());
+            Ok(())
+        });
+        mock_feedback_repo
+            .expect_list()
+            .returning(move |after, limit| {
+                let saved = saved.lock().unwrap();
+                let start = match after {
+                    Some(after) => saved.iter().position(|f| f.id == after).unwrap() + 1,
+                    None => 0,
+                };
+                Ok(saved.iter().skip(start).take(limit).cloned().collect())
+            });
+
+        let service =
+            FeedbackService::new(3, Arc::new(mock_feedback_repo), Arc::new(mock_unswer_repo));
+        for request in requests {
+            service.submit(request).await.unwrap();
+        }
+
+        let report = service.report(2).await.unwrap();
+
+        assert_eq!((report.up, report.down, report.corrections), (1, 3, 1));
+        // Документ, процитированный в ответе дважды, считается один раз;
+        // при равном числе отрицательных выше тот, у кого нет положительных
+        assert_eq!(
+            report.documents,
+            vec![
+                DocumentFeedback {
+                    doc_id: doc_a,
+                    up: 0,
+                    down: 2,
+                },
+                DocumentFeedback {
+                    doc_id: doc_c,
+                    up: 0,
+                    down: 1,
+                },
+            ]
+        );
+        assert_eq!(report.chunks.len(), 2);
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt::Error;
+use std::sync::Arc;
+
+use crate::domain::groundedness::ClaimVerifier;
+use crate::domain::unswer::LLM;
+use crate::service::citation::{is_sentence_end, parse_citations};
+use crate::service::rerank::parse_scores;
+
+// Утверждение с оценкой не ниже порога считается подтверждённым контекстом
+const SUPPORT_THRESHOLD: f32 = 0.5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Groundedness {
+    // Доля подтверждённых утверждений ответа, от 0 до 1
+    pub score: f32,
+    pub unsupported_claims: Vec<String>,
+}
+
+// Утверждения ответа — его предложения без ссылок [n]
+pub fn split_claims(answer: &str) -> Vec<String> {
+    // С пустым контекстом все ссылки считаются битыми и удаляются
+    let (text, _) = parse_citations(answer, &[]);
+    let mut claims = Vec::new();
+    let mut claim = String::new();
+    for c in text.chars() {
+        claim.push(c);
+        if is_sentence_end(c) {
+            claims.push(std::mem::take(&mut claim));
+        }
+    }
+    claims.push(claim);
+
+    claims
+        .into_iter()
+        .map(|claim| claim.trim().to_string())
+        .filter(|claim| claim.chars().any(char::is_alphanumeric))
+        .collect()
+}
+
+// Ответ без утверждений считается обоснованным
+pub async fn check_groundedness(
+    verifier: &dyn ClaimVerifier,
+    answer: &str,
+    passages: &[String],
+) -> Result<Groundedness, Error> {
+    let claims = split_claims(answer);
+    if claims.is_empty() {
+        return Ok(Groundedness {
+            score: 1.0,
+            unsupported_claims: Vec::new(),
+        });
+    }
+
+    let scores = verifier.verify(&claims, passages).await?;
+    // Утверждение, которому верификатор не дал оценки, считается неподтверждённым
+    let unsupported_claims: Vec<String> = claims
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| scores.get(*i).is_none_or(|&s| s < SUPPORT_THRESHOLD))
+        .map(|(_, claim)| claim.clone())
+        .collect();
+
+    Ok(Groundedness {
+        score: 1.0 - unsupported_claims.len() as f32 / claims.len() as f32,
+        unsupported_claims,
+    })
+}
+
+// Проверка второй генеративной моделью: один запрос на все утверждения ответа,
+// модель ставит каждому оценку от 0 до 10
+pub struct LlmClaimVerifier {
+    llm: Arc<dyn LLM>,
+}
+
+impl LlmClaimVerifier {
+    pub fn new(llm: Arc<dyn LLM>) -> Self {
+        Self { llm }
+    }
+}
+
+fn prompt(claims: &[String], passages: &[String]) -> String {
+    let mut prompt = String::from(
+        "Оцени, насколько каждое утверждение следует из фрагментов, по шкале от 0 до 10: \
+         0 — не упоминается или противоречит, 10 — прямо следует.\n\
+         Ответь строками вида «номер: оценка» без пояснений.\n\nФрагменты:\n",
+    );
+    for passage in passages {
+        prompt.push_str(&format!("\n{passage}\n"));
+    }
+    prompt.push_str("\nУтверждения:\n");
+    for (i, claim) in claims.iter().enumerate() {
+        prompt.push_str(&format!("[{}] {}\n", i + 1, claim));
+    }
+    prompt
+}
+
+#[async_trait::async_trait]
+impl ClaimVerifier for LlmClaimVerifier {
+    async fn verify(&self, claims: &[String], passages: &[String]) -> Result<Vec<f32>, Error> {
+        if claims.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let reply = self.llm.complete(prompt(claims, passages)).await?;
+        let scores = parse_scores(&reply);
+        Ok((1..=claims.len())
+            .map(|i| (scores.get(&i).copied().unwrap_or(0.0) / 10.0).clamp(0.0, 1.0))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::unswer::MockLLM;
+
+    #[tokio::test]
+    async fn test_unsupported_claims_are_reported() {
+        let mut mock_llm = MockLLM::new();
+        mock_llm
+            .expect_complete()
+            .withf(|prompt| {
+                prompt.contains("\nRust — язык.\n")
+                    && prompt.contains("[1] Rust — язык программирования.\n")
+                    && prompt.contains("[2] Его создали в 1985 году.\n")
+            })
+            .returning(|_| Ok("1: 9\n2: 1".into()));
+        let verifier = LlmClaimVerifier::new(Arc::new(mock_llm));
+
+        let groundedness = check_groundedness(
+            &verifier,
+            "Rust — язык программирования [1]. Его создали в 1985 году.[1]",
+            &["Rust — язык.".to_string()],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(groundedness.score, 0.5);
+        assert_eq!(
+            groundedness.unsupported_claims,
+            vec!["Его создали в 1985 году."]
+        );
+    }
+}
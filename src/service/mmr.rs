@@ -0,0 +1,94 @@
+use crate::domain::embedding::{Metric, ScoredChunk};
+
+// Maximal marginal relevance: жадно выбирает чанк с наибольшим
+// lambda * близость_к_вопросу - (1 - lambda) * max(близость_к_уже_выбранным).
+// lambda = 1 даёт обычный поиск, lambda = 0 — максимальное разнообразие.
+// Оценки в результате остаются оценками поиска, меняется только набор и порядок.
+pub fn select_mmr(
+    query: &[f32],
+    candidates: &[(ScoredChunk, Vec<f32>)],
+    metric: Metric,
+    lambda: f32,
+    top_k: usize,
+) -> Vec<ScoredChunk> {
+    let relevance: Vec<f32> = candidates
+        .iter()
+        .map(|(_, vec)| metric.similarity(query, vec))
+        .collect();
+    // Наибольшая близость каждого кандидата к уже выбранным
+    let mut redundancy = vec![f32::MIN; candidates.len()];
+    let mut selected = vec![false; candidates.len()];
+    let mut result = Vec::with_capacity(top_k.min(candidates.len()));
+
+    while result.len() < top_k {
+        let best = (0..candidates.len())
+            .filter(|&i| !selected[i])
+            .map(|i| {
+                let penalty = if result.is_empty() {
+                    0.0
+                } else {
+                    redundancy[i]
+                };
+                (i, lambda * relevance[i] - (1.0 - lambda) * penalty)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0.cmp(&a.0)));
+        let Some((best, _)) = best else {
+            break;
+        };
+
+        selected[best] = true;
+        result.push(candidates[best].0);
+        for i in 0..candidates.len() {
+            if !selected[i] {
+                let similarity = metric.similarity(&candidates[i].1, &candidates[best].1);
+                redundancy[i] = redundancy[i].max(similarity);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ids;
+    use uuid::Uuid;
+
+    fn candidates(vectors: &[[f32; 2]]) -> Vec<(ScoredChunk, Vec<f32>)> {
+        vectors
+            .iter()
+            .enumerate()
+            .map(|(i, vec)| {
+                let chunk = ScoredChunk {
+                    chunk_id: Uuid::from_u128(i as u128),
+                    score: 1.0 - i as f32 * 0.1,
+                };
+                (chunk, vec.to_vec())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_near_duplicates_are_skipped() {
+        // 0 и 1 — почти одинаковые абзацы, 2 — другой, но тоже по теме
+        let candidates = candidates(&[[1.0, 0.4], [1.0, 0.38], [0.6, 0.8]]);
+        let query = [1.0, 0.5];
+
+        let diverse = select_mmr(&query, &candidates, Metric::Cosine, 0.5, 2);
+        let relevant = select_mmr(&query, &candidates, Metric::Cosine, 1.0, 2);
+
+        assert_eq!(ids(&diverse), vec![0, 2]);
+        assert_eq!(ids(&relevant), vec![0, 1]);
+        assert_eq!(diverse[1].score, candidates[2].0.score);
+    }
+
+    #[test]
+    fn test_top_k_larger_than_candidates() {
+        let candidates = candidates(&[[1.0, 0.0], [0.0, 1.0]]);
+
+        let found = select_mmr(&[1.0, 0.0], &candidates, Metric::Dot, 0.7, 5);
+
+        assert_eq!(ids(&found), vec![0, 1]);
+        assert!(select_mmr(&[1.0, 0.0], &[], Metric::Dot, 0.7, 5).is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::domain::document::Chunk;
+use crate::domain::unswer::ContextEntry;
+
+struct Span {
+    // Ранг лучшего найденного чанка внутри окна
+    rank: usize,
+    first: usize,
+    last: usize,
+    entry: ContextEntry,
+}
+
+// Small-to-big: каждый найденный чанк заменяется окном из window чанков до и после него
+// в том же документе. Пересекающиеся и соседние окна одного документа склеиваются:
+// склейка наследует id лучшего по рангу чанка и наибольшую из оценок, id остальных
+// найденных чанков попадают в merged_chunks_id. Порядок результата — по рангу.
+// documents — чанки документов в порядке position.
+pub fn expand_neighbours(
+    hits: Vec<ContextEntry>,
+    documents: &HashMap<Uuid, Vec<Chunk>>,
+    window: usize,
+) -> Vec<ContextEntry> {
+    let mut spans: HashMap<Uuid, Vec<Span>> = HashMap::new();
+    let mut expanded: Vec<(usize, ContextEntry)> = Vec::new();
+
+    for (rank, entry) in hits.into_iter().enumerate() {
+        let position = documents
+            .get(&entry.doc_id)
+            .and_then(|chunks| chunks.iter().position(|c| c.id == entry.chunk_id));
+        match position {
+            Some(i) => spans.entry(entry.doc_id).or_default().push(Span {
+                rank,
+                first: i.saturating_sub(window),
+                last: (i + window).min(documents[&entry.doc_id].len() - 1),
+                entry,
+            }),
+            // Документ успели изменить, оставляем чанк как есть
+            None => expanded.push((rank, entry)),
+        }
+    }
+
+    for (doc_id, mut doc_spans) in spans {
+        let chunks = &documents[&doc_id];
+        doc_spans.sort_by_key(|s| s.first);
+
+        let mut merged: Vec<Span> = Vec::new();
+        for span in doc_spans {
+            match merged.last_mut() {
+                Some(prev) if span.first <= prev.last + 1 => {
+                    prev.last = prev.last.max(span.last);
+                    let mut other = span.entry.chunk_id;
+                    if span.rank < prev.rank {
+                        prev.rank = span.rank;
+                        other = std::mem::replace(&mut prev.entry.chunk_id, other);
+                    }
+                    prev.entry.merged_chunks_id.push(other);
+                    prev.entry
+                        .merged_chunks_id
+                        .extend(span.entry.merged_chunks_id);
+                    prev.entry.score = prev.entry.score.max(span.entry.score);
+                }
+                _ => merged.push(span),
+            }
+        }
+
+        for mut span in merged {
+            span.entry.text = chunks[span.first..=span.last]
+                .iter()
+                .map(|c| c.text.as_str())
+                .collect();
+            expanded.push((span.rank, span.entry));
+        }
+    }
+
+    expanded.sort_by_key(|(rank, _)| *rank);
+    expanded.into_iter().map(|(_, entry)| entry).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(texts: &[&str]) -> Vec<Chunk> {
+        let doc_id = Uuid::new_v4();
+        texts
+            .iter()
+            .enumerate()
+            .map(|(position, text)| {
+                let mut chunk = Chunk::new(doc_id, text.to_string());
+                chunk.position = position;
+                chunk
+            })
+            .collect()
+    }
+
+    fn hit(chunk: &Chunk, score: f32) -> ContextEntry {
+        ContextEntry {
+            chunk_id: chunk.id,
+            doc_id: chunk.doc_id,
+            title: None,
+            text: chunk.text.clone(),
+            score,
+            merged_chunks_id: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_hits_expand_into_windows() {
+        let doc = document(&["a", "b", "c", "d", "e", "f", "g"]);
+        let other = document(&["x", "y"]);
+        let documents = HashMap::from([
+            (doc[0].doc_id, doc.clone()),
+            (other[0].doc_id, other.clone()),
+        ]);
+
+        let expanded = expand_neighbours(
+            vec![hit(&other[1], 0.9), hit(&doc[0], 0.8), hit(&doc[5], 0.7)],
+            &documents,
+            1,
+        );
+
+        let texts: Vec<&str> = expanded.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(texts, vec!["xy", "ab", "efg"]);
+        assert_eq!(expanded[2].chunk_id, doc[5].id);
+    }
+
+    #[test]
+    fn test_overlapping_windows_are_merged() {
+        let doc = document(&["a", "b", "c", "d", "e", "f", "g"]);
+        let documents = HashMap::from([(doc[0].doc_id, doc.clone())]);
+
+        // Окна [3, 5], [1, 3] и [5, 6] сливаются в одно
+        let expanded = expand_neighbours(
+            vec![hit(&doc[4], 0.6), hit(&doc[2], 0.9), hit(&doc[6], 0.5)],
+            &documents,
+            1,
+        );
+
+        assert_eq!(expanded.len(), 1);
+        assert_eq!(expanded[0].text, "bcdefg");
+        assert_eq!(expanded[0].chunk_id, doc[4].id);
+        assert_eq!(expanded[0].score, 0.9);
+        assert_eq!(expanded[0].merged_chunks_id, vec![doc[2].id, doc[6].id]);
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::domain::document::Document;
+use crate::domain::unswer::ContextEntry;
+
+// Текст документа, который целиком помещается в max_chars: сам документ или его краткое содержание
+fn parent_text(document: &Document, max_chars: usize) -> Option<&str> {
+    if document.text.chars().count() <= max_chars {
+        return Some(&document.text);
+    }
+    document
+        .summary
+        .as_deref()
+        .filter(|summary| summary.chars().count() <= max_chars)
+}
+
+// Найденные чанки короткого документа заменяются одним фрагментом с текстом документа
+// на месте лучшего по рангу чанка; его id и оценка остаются у фрагмента,
+// id остальных чанков документа попадают в merged_chunks_id. Чанки документов, которые не помещаются, остаются как есть.
+pub fn to_parent_documents(
+    hits: Vec<ContextEntry>,
+    documents: &HashMap<Uuid, Document>,
+    max_chars: usize,
+) -> Vec<ContextEntry> {
+    // Документ -> индекс его фрагмента в context
+    let mut parents: HashMap<Uuid, usize> = HashMap::new();
+    let mut context: Vec<ContextEntry> = Vec::with_capacity(hits.len());
+
+    for entry in hits {
+        let parent = documents
+            .get(&entry.doc_id)
+            .and_then(|document| parent_text(document, max_chars));
+        match parent {
+            Some(text) => match parents.get(&entry.doc_id) {
+                Some(&i) => {
+                    let parent = &mut context[i];
+                    parent.merged_chunks_id.push(entry.chunk_id);
+                    parent.merged_chunks_id.extend(entry.merged_chunks_id);
+                }
+                None => {
+                    parents.insert(entry.doc_id, context.len());
+                    context.push(ContextEntry {
+                        text: text.to_string(),
+                        ..entry
+                    });
+                }
+            },
+            None => context.push(entry),
+        }
+    }
+    context
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(doc_id: Uuid, text: &str, score: f32) -> ContextEntry {
+        ContextEntry {
+            chunk_id: Uuid::new_v4(),
+            doc_id,
+            title: None,
+            text: text.to_string(),
+            score,
+            merged_chunks_id: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_short_documents_replace_their_chunks() {
+        let short = Document::new("Короткая заметка целиком".into());
+        let mut long = Document::new("Длинный документ ".repeat(10));
+        let mut summarized = Document::new("Ещё один длинный документ ".repeat(10));
+        summarized.summary = Some("Кратко".into());
+        long.summary = Some("Слишком длинное краткое содержание".repeat(3));
+        let documents = HashMap::from([
+            (short.id, short.clone()),
+            (long.id, long.clone()),
+            (summarized.id, summarized.clone()),
+        ]);
+
+        let hits = vec![
+            hit(long.id, "Длинный", 0.9),
+            hit(short.id, "Короткая", 0.8),
+            hit(summarized.id, "Ещё один", 0.7),
+            hit(short.id, "заметка", 0.6),
+            hit(long.id, "документ", 0.5),
+        ];
+        let (best_short, other_short) = (hits[1].chunk_id, hits[3].chunk_id);
+
+        let context = to_parent_documents(hits, &documents, 30);
+
+        let texts: Vec<&str> = context.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec!["Длинный", "Короткая заметка целиком", "Кратко", "документ"]
+        );
+        assert_eq!(context[1].chunk_id, best_short);
+        assert_eq!(context[1].score, 0.8);
+        assert_eq!(context[1].merged_chunks_id, vec![other_short]);
+    }
+}
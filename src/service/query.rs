@@ -0,0 +1,185 @@
+use std::fmt::Error;
+use std::sync::Arc;
+
+use crate::domain::prompt::HistoryTurn;
+use crate::domain::question::{QueryRewrite, RewriteKind};
+use crate::domain::unswer::LLM;
+
+const STANDALONE: &str = "ВОПРОС:";
+const PARAPHRASE: &str = "ПЕРЕФРАЗ:";
+const HYPOTHETICAL: &str = "ОТВЕТ:";
+
+// Переписывает вопрос пользователя для поиска: самостоятельная формулировка,
+// несколько перефразировок и, по желанию, гипотетический ответ (HyDE)
+pub struct QueryRewriter {
+    llm: Arc<dyn LLM>,
+    paraphrases: usize,
+    hypothetical: bool,
+}
+
+impl QueryRewriter {
+    pub fn new(llm: Arc<dyn LLM>, paraphrases: usize) -> Self {
+        Self {
+            llm,
+            paraphrases,
+            hypothetical: false,
+        }
+    }
+
+    pub fn with_hypothetical_answer(mut self) -> Self {
+        self.hypothetical = true;
+        self
+    }
+
+    fn prompt(&self, question: &str) -> String {
+        let mut prompt = format!(
+            "Перепиши вопрос пользователя для поиска по базе знаний.\n\
+             Первой строкой дай самостоятельную формулировку без местоимений и отсылок к разговору: {STANDALONE} <текст>\n\
+             Затем {} других формулировок того же вопроса, каждую строкой {PARAPHRASE} <текст>\n",
+            self.paraphrases
+        );
+        if self.hypothetical {
+            prompt.push_str(&format!(
+                "Последней строкой напиши краткий правдоподобный ответ: {HYPOTHETICAL} <текст>\n"
+            ));
+        }
+        prompt.push_str(&format!("\nВопрос: {question}"));
+        prompt
+    }
+
+    pub async fn rewrite(&self, question: &str) -> Result<Vec<QueryRewrite>, Error> {
+        let reply = self.llm.complete(self.prompt(question)).await?;
+        Ok(self.parse(question, &reply))
+    }
+
+    // Строки без известного префикса пропускаются, дубликаты исходного вопроса отбрасываются
+    fn parse(&self, question: &str, reply: &str) -> Vec<QueryRewrite> {
+        let mut rewrites: Vec<QueryRewrite> = Vec::new();
+        let mut paraphrases = 0;
+
+        for line in reply.lines().map(str::trim) {
+            let (kind, text) = if let Some(text) = line.strip_prefix(STANDALONE) {
+                (RewriteKind::Standalone, text)
+            } else if let Some(text) = line.strip_prefix(PARAPHRASE) {
+                (RewriteKind::Paraphrase, text)
+            } else if let Some(text) = line.strip_prefix(HYPOTHETICAL) {
+                (RewriteKind::Hypothetical, text)
+            } else {
+                continue;
+            };
+            let text = text.trim();
+
+            let allowed = match kind {
+                RewriteKind::Standalone => !rewrites.iter().any(|r| r.kind == kind),
+                RewriteKind::Paraphrase => paraphrases < self.paraphrases,
+                RewriteKind::Hypothetical => {
+                    self.hypothetical && !rewrites.iter().any(|r| r.kind == kind)
+                }
+            };
+            let duplicate = text.to_lowercase() == question.trim().to_lowercase()
+                || rewrites.iter().any(|r| r.text == text);
+            if !allowed || duplicate || text.is_empty() {
+                continue;
+            }
+
+            if kind == RewriteKind::Paraphrase {
+                paraphrases += 1;
+            }
+            rewrites.push(QueryRewrite {
+                kind,
+                text: text.to_string(),
+            });
+        }
+        rewrites
+    }
+}
+
+// Переписывает уточняющий вопрос разговора в самостоятельный,
+// чтобы по нему можно было искать без предыдущих ходов
+pub struct QuestionCondenser {
+    llm: Arc<dyn LLM>,
+    // Сколько последних ходов разговора показывать модели
+    max_turns: usize,
+}
+
+impl QuestionCondenser {
+    pub fn new(llm: Arc<dyn LLM>, max_turns: usize) -> Self {
+        Self { llm, max_turns }
+    }
+
+    fn prompt(&self, question: &str, history: &[HistoryTurn]) -> String {
+        let mut prompt = String::from(
+            "Перепиши последний вопрос пользователя так, чтобы он был понятен без разговора: \
+             раскрой местоимения и отсылки к предыдущим ходам. \
+             Ответь одной строкой без пояснений.\n\nРазговор:\n",
+        );
+        let skip = history.len().saturating_sub(self.max_turns);
+        for turn in &history[skip..] {
+            prompt.push_str(&format!(
+                "\nВопрос: {}\nОтвет: {}\n",
+                turn.question, turn.unswer
+            ));
+        }
+        prompt.push_str(&format!("\nПоследний вопрос: {question}"));
+        prompt
+    }
+
+    // Без истории вопрос возвращается как есть, модель не вызывается
+    pub async fn condense(&self, question: &str, history: &[HistoryTurn]) -> Result<String, Error> {
+        if history.is_empty() || self.max_turns == 0 {
+            return Ok(question.to_string());
+        }
+
+        let reply = self.llm.complete(self.prompt(question, history)).await?;
+        let condensed = reply.lines().map(str::trim).find(|line| !line.is_empty());
+        let condensed = condensed
+            .map(|line| line.strip_prefix(STANDALONE).unwrap_or(line).trim())
+            .filter(|line| !line.is_empty());
+        Ok(condensed.unwrap_or(question).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::unswer::MockLLM;
+
+    #[tokio::test]
+    async fn test_rewrites_are_parsed_from_reply() {
+        let mut mock_llm = MockLLM::new();
+        mock_llm
+            .expect_complete()
+            .withf(|prompt| {
+                prompt.ends_with("Вопрос: а он быстрый?") && prompt.contains(HYPOTHETICAL)
+            })
+            .returning(|_| {
+                Ok("ВОПРОС: Rust быстрый язык?\n\
+                    Вот варианты:\n\
+                    ПЕРЕФРАЗ: Насколько быстр Rust?\n\
+                    ПЕРЕФРАЗ: Rust быстрый язык?\n\
+                    ПЕРЕФРАЗ: Какова производительность Rust?\n\
+                    ПЕРЕФРАЗ: Лишняя формулировка\n\
+                    ОТВЕТ: Rust сопоставим по скорости с C++."
+                    .into())
+            });
+
+        let rewrites = QueryRewriter::new(Arc::new(mock_llm), 2)
+            .with_hypothetical_answer()
+            .rewrite("а он быстрый?")
+            .await
+            .unwrap();
+
+        let kinds: Vec<RewriteKind> = rewrites.iter().map(|r| r.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                RewriteKind::Standalone,
+                RewriteKind::Paraphrase,
+                RewriteKind::Paraphrase,
+                RewriteKind::Hypothetical
+            ]
+        );
+        assert_eq!(rewrites[2].text, "Какова производительность Rust?");
+        assert_eq!(rewrites[3].text, "Rust сопоставим по скорости с C++.");
+    }
+}
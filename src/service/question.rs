@@ -1,13 +1,24 @@
 use std::fmt::Error;
 use std::sync::Arc;
 
+use uuid::Uuid;
+
+use crate::domain::conversation::{ConversationRepo, ConversationTurn};
 use crate::domain::embedding::{QuestionEmbeddingRepo, QuestionEmbending, TextVectorizer};
 use crate::domain::question::{Question, QuestionRepo};
+use crate::service::query::{QueryRewriter, QuestionCondenser};
 
 pub struct QuestionService {
     question_repo: Arc<dyn QuestionRepo>,
     embedding_repo: Arc<dyn QuestionEmbeddingRepo>,
     vectorizer: Arc<dyn TextVectorizer>,
+    query_rewriter: Option<QueryRewriter>,
+    conversations: Option<Conversations>,
+}
+
+struct Conversations {
+    repo: Arc<dyn ConversationRepo>,
+    condenser: QuestionCondenser,
 }
 
 impl QuestionService {
@@ -17,16 +28,76 @@ impl QuestionService {
         vectorizer: Arc<dyn TextVectorizer>,
     ) -> Self {
         Self {
-            question_repo: question_repo,
-            embedding_repo: embedding_repo,
-            vectorizer: vectorizer,
+            question_repo,
+            embedding_repo,
+            vectorizer,
+            query_rewriter: None,
+            conversations: None,
         }
     }
+
+    // Вопрос дополнительно переписывается для поиска, переформулировки сохраняются вместе с ним
+    pub fn with_query_rewriter(mut self, query_rewriter: QueryRewriter) -> Self {
+        self.query_rewriter = Some(query_rewriter);
+        self
+    }
+
+    // Уточняющие вопросы разговора переписываются в самостоятельные до построения эмбеддинга
+    pub fn with_conversations(
+        mut self,
+        repo: Arc<dyn ConversationRepo>,
+        condenser: QuestionCondenser,
+    ) -> Self {
+        self.conversations = Some(Conversations { repo, condenser });
+        self
+    }
 }
 
 impl QuestionService {
-    pub async fn process_new_question(&self, text: &str) -> Result<(), Error> {
-        let question = Arc::new(Question::new(text.to_string()));
+    // Возвращает id вопроса, по которому затем запрашивается ответ
+    pub async fn process_new_question(&self, text: &str) -> Result<Uuid, Error> {
+        self.process(Question::new(text.to_string())).await
+    }
+
+    // Вопрос добавляется в конец разговора; ответ на него запишет UnswerService
+    pub async fn process_follow_up(
+        &self,
+        conversation_id: Uuid,
+        text: &str,
+    ) -> Result<Uuid, Error> {
+        let Conversations { repo, condenser } = self.conversations.as_ref().ok_or(Error)?;
+        let conversation = repo.read(conversation_id).await?;
+
+        let mut question = Question::new(text.to_string());
+        let standalone = condenser
+            .condense(text, &conversation.history(question.id))
+            .await?;
+        question.conversation_id = Some(conversation_id);
+        question.standalone = (standalone != text).then_some(standalone);
+
+        let turn = ConversationTurn {
+            question_id: question.id,
+            question: question.text.clone(),
+            unswer_id: None,
+            unswer: None,
+        };
+        let question_id = self.process(question).await?;
+
+        repo.append_turn(conversation_id, &turn).await?;
+        Ok(question_id)
+    }
+
+    async fn process(&self, mut question: Question) -> Result<Uuid, Error> {
+        // Без переформулировок вопрос ищется только по исходному тексту,
+        // поэтому сбой модели не мешает сохранить вопрос
+        if let Some(query_rewriter) = &self.query_rewriter {
+            match query_rewriter.rewrite(question.search_text()).await {
+                Ok(rewrites) => question.rewrites = rewrites,
+                Err(err) => eprintln!("question {}: rewrite failed: {err:?}", question.id),
+            }
+        }
+
+        let question = Arc::new(question);
         let question_clone = question.clone();
         let repo_clone = self.question_repo.clone();
 
@@ -48,10 +119,206 @@ impl QuestionService {
             Ok::<(), Error>(())
         });
 
-        // Ждём обе задачи (или можно не ждать, если "fire and forget")
+        // Эмбеддинги переформулировок
+        let mut rewrite_handles = Vec::with_capacity(question.rewrites.len());
+        for rewrite in 0..question.rewrites.len() {
+            let vectorizer = self.vectorizer.clone();
+            let embedding_repo = self.embedding_repo.clone();
+            let question = question.clone();
+
+            rewrite_handles.push(tokio::spawn(async move {
+                let embedding =
+                    QuestionEmbending::for_rewrite(&question, rewrite, vectorizer.as_ref()).await?;
+                embedding_repo.save(&embedding).await?;
+                Ok::<(), Error>(())
+            }));
+        }
+
+        // Ждём все задачи (или можно не ждать, если "fire and forget")
         save_handle.await.unwrap()?;
         embed_handle.await.unwrap()?;
+        for handle in rewrite_handles {
+            handle.await.unwrap()?;
+        }
+
+        Ok(question.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::conversation::{Conversation, MockConversationRepo};
+    use crate::domain::embedding::{MockQuestionEmbeddingRepo, MockTextVectorizer};
+    use crate::domain::question::{MockQuestionRepo, RewriteKind};
+    use crate::domain::unswer::MockLLM;
+
+    #[tokio::test]
+    async fn test_rewrites_are_saved_and_embedded() {
+        let mut mock_llm = MockLLM::new();
+        mock_llm
+            .expect_complete()
+            .returning(|_| Ok("ВОПРОС: Как настроить Rust?\nПЕРЕФРАЗ: Установка Rust".into()));
+
+        let mut mock_question_repo = MockQuestionRepo::new();
+        mock_question_repo
+            .expect_save()
+            .withf(|q| {
+                q.text == "а как его настроить?"
+                    && q.rewrites.len() == 2
+                    && q.rewrites[0].kind == RewriteKind::Standalone
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let mut mock_vectorizer = MockTextVectorizer::new();
+        mock_vectorizer
+            .expect_model_id()
+            .return_const("test-model".to_string());
+        mock_vectorizer
+            .expect_vectorize()
+            .times(3)
+            .returning(|text| Ok(vec![text.chars().count() as f32]));
+
+        let mut mock_embedding_repo = MockQuestionEmbeddingRepo::new();
+        mock_embedding_repo
+            .expect_save()
+            .withf(|e| match e.rewrite {
+                None => e.vec == vec![20.0],
+                Some(0) => e.vec == vec![19.0],
+                Some(1) => e.vec == vec![14.0],
+                Some(_) => false,
+            })
+            .times(3)
+            .returning(|_| Ok(()));
+
+        let service = QuestionService::new(
+            Arc::new(mock_question_repo),
+            Arc::new(mock_embedding_repo),
+            Arc::new(mock_vectorizer),
+        )
+        .with_query_rewriter(QueryRewriter::new(Arc::new(mock_llm), 1));
+
+        service
+            .process_new_question("а как его настроить?")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_question_is_saved_when_rewrite_fails() {
+        let mut mock_llm = MockLLM::new();
+        mock_llm.expect_complete().returning(|_| Err(Error));
+
+        let mut mock_question_repo = MockQuestionRepo::new();
+        mock_question_repo
+            .expect_save()
+            .withf(|q| q.text == "а как его настроить?" && q.rewrites.is_empty())
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let mut mock_vectorizer = MockTextVectorizer::new();
+        mock_vectorizer
+            .expect_model_id()
+            .return_const("test-model".to_string());
+        mock_vectorizer
+            .expect_vectorize()
+            .times(1)
+            .returning(|_| Ok(vec![1.0]));
+
+        let mut mock_embedding_repo = MockQuestionEmbeddingRepo::new();
+        mock_embedding_repo
+            .expect_save()
+            .withf(|e| e.rewrite.is_none())
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let service = QuestionService::new(
+            Arc::new(mock_question_repo),
+            Arc::new(mock_embedding_repo),
+            Arc::new(mock_vectorizer),
+        )
+        .with_query_rewriter(QueryRewriter::new(Arc::new(mock_llm), 1));
+
+        service
+            .process_new_question("а как его настроить?")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_follow_up_is_condensed_and_added_to_conversation() {
+        let mut conversation = Conversation::new();
+        let conversation_id = conversation.id;
+        conversation.turns.push(ConversationTurn {
+            question_id: Uuid::new_v4(),
+            question: "Что нового в Rust 1.80?".into(),
+            unswer_id: Some(Uuid::new_v4()),
+            unswer: Some("Ленивые ячейки.".into()),
+        });
+
+        let mut mock_llm = MockLLM::new();
+        mock_llm
+            .expect_complete()
+            .withf(|prompt| {
+                prompt.contains("Вопрос: Что нового в Rust 1.80?\nОтвет: Ленивые ячейки.")
+                    && prompt.ends_with("Последний вопрос: а в 1.81?")
+            })
+            .returning(|_| Ok("Что нового в Rust 1.81?".into()));
+
+        let mut mock_conversation_repo = MockConversationRepo::new();
+        mock_conversation_repo
+            .expect_read()
+            .return_once(move |_| Ok(conversation));
+        mock_conversation_repo
+            .expect_append_turn()
+            .withf(move |id, turn| {
+                *id == conversation_id && turn.question == "а в 1.81?" && turn.unswer.is_none()
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let mut mock_question_repo = MockQuestionRepo::new();
+        mock_question_repo
+            .expect_save()
+            .withf(move |q| {
+                q.text == "а в 1.81?"
+                    && q.standalone.as_deref() == Some("Что нового в Rust 1.81?")
+                    && q.conversation_id == Some(conversation_id)
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        // Эмбеддинг строится по самостоятельной формулировке
+        let mut mock_vectorizer = MockTextVectorizer::new();
+        mock_vectorizer
+            .expect_model_id()
+            .return_const("test-model".to_string());
+        mock_vectorizer
+            .expect_vectorize()
+            .withf(|text| text == "Что нового в Rust 1.81?")
+            .times(1)
+            .returning(|_| Ok(vec![1.0]));
+
+        let mut mock_embedding_repo = MockQuestionEmbeddingRepo::new();
+        mock_embedding_repo
+            .expect_save()
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let service = QuestionService::new(
+            Arc::new(mock_question_repo),
+            Arc::new(mock_embedding_repo),
+            Arc::new(mock_vectorizer),
+        )
+        .with_conversations(
+            Arc::new(mock_conversation_repo),
+            QuestionCondenser::new(Arc::new(mock_llm), 4),
+        );
 
-        Ok(())
+        service
+            .process_follow_up(conversation_id, "а в 1.81?")
+            .await
+            .unwrap();
     }
 }
@@ -0,0 +1,468 @@
+use std::collections::HashSet;
+use std::fmt::Error;
+use std::sync::Arc;
+
+use tokio::sync::watch;
+use uuid::Uuid;
+
+use crate::domain::document::{Chunk, ChunkRepo};
+use crate::domain::embedding::{
+    ActiveEmbeddingModel, ChunkEmbending, ChunkEmbendingRepo, EmbeddingModel, TextVectorizer,
+    VectorIndexRegistry,
+};
+use crate::domain::reembedding::{ReembeddingCheckpoint, ReembeddingCheckpointRepo};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReembeddingProgress {
+    pub processed: usize,
+    pub total: usize,
+    pub completed: bool,
+}
+
+// Фоновая переиндексация всех чанков новой моделью.
+// Новый индекс строится рядом со старым, модель переключается только в самом конце.
+// Сервисы должны работать через тот же ActiveEmbeddingModel: чанки, записанные
+// во время переиндексации, дописываются в новый индекс перед переключением.
+pub struct ReembeddingService {
+    pub batch_size: usize,
+    chunk_repo: Arc<dyn ChunkRepo>,
+    vectorizer: Arc<dyn TextVectorizer>,
+    index_registry: Arc<dyn VectorIndexRegistry>,
+    checkpoint_repo: Arc<dyn ReembeddingCheckpointRepo>,
+    active_model: Arc<ActiveEmbeddingModel>,
+    semaphore: Arc<tokio::sync::Semaphore>,
+    progress: watch::Sender<ReembeddingProgress>,
+}
+
+impl ReembeddingService {
+    pub fn new(
+        batch_size: usize,
+        chunk_repo: Arc<dyn ChunkRepo>,
+        vectorizer: Arc<dyn TextVectorizer>,
+        index_registry: Arc<dyn VectorIndexRegistry>,
+        checkpoint_repo: Arc<dyn ReembeddingCheckpointRepo>,
+        active_model: Arc<ActiveEmbeddingModel>,
+        semaphore: Arc<tokio::sync::Semaphore>,
+    ) -> Self {
+        Self {
+            batch_size,
+            chunk_repo,
+            vectorizer,
+            index_registry,
+            checkpoint_repo,
+            active_model,
+            semaphore,
+            progress: watch::channel(ReembeddingProgress::default()).0,
+        }
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<ReembeddingProgress> {
+        self.progress.subscribe()
+    }
+}
+
+impl ReembeddingService {
+    pub async fn run(&self) -> Result<(), Error> {
+        let model_id = self.vectorizer.model_id();
+
+        // 1. Продолжаем с последней контрольной точки, если она есть
+        let saved = self.checkpoint_repo.read(&model_id).await?;
+        let resumed = saved.is_some();
+        let mut checkpoint = saved.unwrap_or_else(|| ReembeddingCheckpoint::new(model_id.clone()));
+
+        let index = self.index_registry.open(&model_id).await?;
+        let embending_repo = index.embending_repo.clone();
+        // С этого момента изменённые чанки запоминаются
+        self.active_model.begin_switch(EmbeddingModel {
+            vectorizer: self.vectorizer.clone(),
+            index,
+        });
+        let total = self.chunk_repo.count().await?;
+        self.report(&checkpoint, total);
+
+        // 2. Пачками векторизуем чанки и пишем в новый индекс
+        while !checkpoint.completed {
+            let chunks = self
+                .chunk_repo
+                .list(checkpoint.last_chunk_id, self.batch_size)
+                .await?;
+
+            let Some(last_chunk) = chunks.last() else {
+                checkpoint.completed = true;
+                self.checkpoint_repo.save(&checkpoint).await?;
+                break;
+            };
+            let last_chunk_id = last_chunk.id;
+            let batch_len = chunks.len();
+            self.embed(chunks, &embending_repo).await?;
+
+            // Пачка целиком записана — фиксируем контрольную точку
+            checkpoint.last_chunk_id = Some(last_chunk_id);
+            checkpoint.processed += batch_len;
+            self.checkpoint_repo.save(&checkpoint).await?;
+            self.report(&checkpoint, total);
+        }
+
+        // 3. До перезапуска изменения не отслеживались, а чанки с id меньше
+        // контрольной точки обход пропустил: дописываем всё, чего нет в новом индексе
+        if resumed {
+            self.fill_missing(&embending_repo).await?;
+        }
+
+        // 4. Дописываем чанки, изменённые во время обхода, и атомарно переключаем модель
+        loop {
+            let changed = self.active_model.take_changed();
+            self.catch_up(&changed, &embending_repo).await?;
+            if self.active_model.try_switch() {
+                break;
+            }
+        }
+        self.report(&checkpoint, total);
+
+        Ok(())
+    }
+
+    pub async fn progress(&self) -> Result<ReembeddingProgress, Error> {
+        let model_id = self.vectorizer.model_id();
+        let checkpoint = self.checkpoint_repo.read(&model_id).await?;
+        let total = self.chunk_repo.count().await?;
+
+        Ok(match checkpoint {
+            Some(checkpoint) => ReembeddingProgress {
+                processed: checkpoint.processed,
+                total,
+                completed: checkpoint.completed,
+            },
+            None => ReembeddingProgress {
+                total,
+                ..Default::default()
+            },
+        })
+    }
+
+    async fn embed(
+        &self,
+        chunks: Vec<Chunk>,
+        embending_repo: &Arc<dyn ChunkEmbendingRepo>,
+    ) -> Result<(), Error> {
+        let mut handles = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            let semaphore = self.semaphore.clone();
+            let vectorizer = self.vectorizer.clone();
+            let embending_repo = embending_repo.clone();
+
+            handles.push(tokio::spawn(async move {
+                let _permit = semaphore.acquire().await;
+                let embending = ChunkEmbending::new(&chunk, vectorizer.as_ref()).await?;
+                embending_repo.save(&embending).await?;
+                Ok::<(), Error>(())
+            }));
+        }
+        for h in handles {
+            h.await.unwrap()?;
+        }
+        Ok(())
+    }
+
+    async fn fill_missing(
+        &self,
+        embending_repo: &Arc<dyn ChunkEmbendingRepo>,
+    ) -> Result<(), Error> {
+        let mut after = None;
+        loop {
+            let chunks = self.chunk_repo.list(after, self.batch_size).await?;
+            let Some(last_chunk) = chunks.last() else {
+                return Ok(());
+            };
+            after = Some(last_chunk.id);
+
+            let mut missing = Vec::new();
+            for chunk in chunks {
+                if embending_repo.read(chunk.id).await.is_err() {
+                    missing.push(chunk);
+                }
+            }
+            self.embed(missing, embending_repo).await?;
+        }
+    }
+
+    // Перезаписывает изменённые чанки; удалённые убираются из нового индекса
+    async fn catch_up(
+        &self,
+        changed: &[Uuid],
+        embending_repo: &Arc<dyn ChunkEmbendingRepo>,
+    ) -> Result<(), Error> {
+        if changed.is_empty() {
+            return Ok(());
+        }
+        let chunks = self.chunk_repo.read_many(changed).await?;
+        let found: HashSet<Uuid> = chunks.iter().map(|chunk| chunk.id).collect();
+        for chunk_id in changed.iter().filter(|id| !found.contains(id)) {
+            // Эмбеддинга удалённого чанка в новом индексе может и не быть
+            let _ = embending_repo.delete(*chunk_id).await;
+        }
+        self.embed(chunks, embending_repo).await
+    }
+
+    fn report(&self, checkpoint: &ReembeddingCheckpoint, total: usize) {
+        self.progress.send_replace(ReembeddingProgress {
+            processed: checkpoint.processed,
+            total,
+            completed: checkpoint.completed,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::document::MockChunkRepo;
+    use crate::domain::embedding::{
+        MockChunkEmbendingRepo, MockTextVectorizer, MockVectorIndexRegistry, MockVectorSearcher,
+        ScoredChunk, VectorIndex, VectorSearcher,
+    };
+    use crate::domain::reembedding::MockReembeddingCheckpointRepo;
+
+    fn vectorizer(model_id: &'static str, vec: Vec<f32>) -> Arc<dyn TextVectorizer> {
+        let mut mock_vectorizer = MockTextVectorizer::new();
+        mock_vectorizer
+            .expect_model_id()
+            .returning(move || model_id.to_string());
+        mock_vectorizer
+            .expect_vectorize()
+            .returning(move |_| Ok(vec.clone()));
+        Arc::new(mock_vectorizer)
+    }
+
+    fn model(
+        vectorizer: Arc<dyn TextVectorizer>,
+        embending_repo: MockChunkEmbendingRepo,
+        searcher: MockVectorSearcher,
+    ) -> EmbeddingModel {
+        EmbeddingModel {
+            index: VectorIndex {
+                model_id: vectorizer.model_id(),
+                embending_repo: Arc::new(embending_repo),
+                searcher: Arc::new(searcher),
+            },
+            vectorizer,
+        }
+    }
+
+    fn registry(model: EmbeddingModel) -> MockVectorIndexRegistry {
+        let mut mock_registry = MockVectorIndexRegistry::new();
+        mock_registry
+            .expect_open()
+            .returning(move |_| Ok(model.index.clone()));
+        mock_registry
+    }
+
+    #[tokio::test]
+    async fn test_run_resumes_from_checkpoint_and_switches_model() {
+        let doc_id = Uuid::new_v4();
+        let resumed_from = Uuid::new_v4();
+        let new_chunk_id = Uuid::new_v4();
+        let new_chunk = Chunk::new(doc_id, "Мороз и солнце".into());
+        let expected_chunk = new_chunk.id;
+        // Записан до перезапуска с id меньше контрольной точки
+        let skipped_chunk = Chunk::new(doc_id, "День чудесный".into());
+        let skipped_id = skipped_chunk.id;
+
+        let mut mock_chunk_repo = MockChunkRepo::new();
+        mock_chunk_repo.expect_count().returning(|| Ok(2));
+        let listed = new_chunk.clone();
+        mock_chunk_repo
+            .expect_list()
+            .withf(move |after, _| *after == Some(resumed_from))
+            .times(1)
+            .returning(move |_, _| Ok(vec![listed.clone()]));
+        mock_chunk_repo
+            .expect_list()
+            .withf(move |after, _| *after == Some(expected_chunk))
+            .times(2)
+            .returning(|_, _| Ok(vec![]));
+        // Повторный обход после перезапуска
+        mock_chunk_repo
+            .expect_list()
+            .withf(|after, _| after.is_none())
+            .times(1)
+            .returning(move |_, _| Ok(vec![skipped_chunk.clone(), new_chunk.clone()]));
+
+        let mut mock_emb_repo = MockChunkEmbendingRepo::new();
+        mock_emb_repo
+            .expect_read()
+            .returning(move |chunk_id| match chunk_id == expected_chunk {
+                true => Ok(ChunkEmbending {
+                    id: Uuid::new_v4(),
+                    chunk_id,
+                    model_id: "model-v2".into(),
+                    vec: vec![0.1, 0.2],
+                    metadata: Default::default(),
+                }),
+                false => Err(Error),
+            });
+        mock_emb_repo
+            .expect_save()
+            .withf(move |e| {
+                [expected_chunk, skipped_id].contains(&e.chunk_id) && e.model_id == "model-v2"
+            })
+            .times(2)
+            .returning(|_| Ok(()));
+
+        let mut new_searcher = MockVectorSearcher::new();
+        new_searcher
+            .expect_search_similar()
+            .returning(move |_, _, _, _| {
+                Ok(vec![ScoredChunk {
+                    chunk_id: new_chunk_id,
+                    score: 1.0,
+                }])
+            });
+        let new_vectorizer = vectorizer("model-v2", vec![0.1, 0.2]);
+        let new_model = model(new_vectorizer.clone(), mock_emb_repo, new_searcher);
+
+        let mut mock_checkpoint_repo = MockReembeddingCheckpointRepo::new();
+        mock_checkpoint_repo
+            .expect_read()
+            .returning(move |model_id| {
+                Ok(Some(ReembeddingCheckpoint {
+                    model_id: model_id.to_string(),
+                    last_chunk_id: Some(resumed_from),
+                    processed: 1,
+                    completed: false,
+                }))
+            });
+        mock_checkpoint_repo.expect_save().returning(|_| Ok(()));
+
+        let mut old_searcher = MockVectorSearcher::new();
+        old_searcher.expect_search_similar().never();
+        let active = Arc::new(ActiveEmbeddingModel::new(
+            model(
+                vectorizer("model-v1", vec![9.0]),
+                MockChunkEmbendingRepo::new(),
+                old_searcher,
+            ),
+            Arc::new(MockChunkRepo::new()),
+        ));
+
+        let service = ReembeddingService::new(
+            10,
+            Arc::new(mock_chunk_repo),
+            new_vectorizer,
+            Arc::new(registry(new_model)),
+            Arc::new(mock_checkpoint_repo),
+            active.clone(),
+            Arc::new(tokio::sync::Semaphore::new(5)),
+        );
+        let progress = service.subscribe();
+
+        service.run().await.unwrap();
+
+        assert_eq!(
+            *progress.borrow(),
+            ReembeddingProgress {
+                processed: 2,
+                total: 2,
+                completed: true
+            }
+        );
+        // Векторизатор переключается вместе с поиском
+        assert_eq!(active.model_id(), "model-v2");
+        assert_eq!(active.vectorize("вопрос").await.unwrap(), vec![0.1, 0.2]);
+        let found = active
+            .search_similar(&[0.1, 0.2], 1, None, None)
+            .await
+            .unwrap();
+        assert_eq!(found[0].chunk_id, new_chunk_id);
+    }
+
+    #[tokio::test]
+    async fn test_chunks_written_during_run_reach_new_index() {
+        let written = Chunk::new(Uuid::new_v4(), "Вечор, ты помнишь".into());
+        let written_id = written.id;
+
+        let mut old_emb_repo = MockChunkEmbendingRepo::new();
+        old_emb_repo.expect_save().times(1).returning(|_| Ok(()));
+        let mut active_chunk_repo = MockChunkRepo::new();
+        let stored = written.clone();
+        active_chunk_repo
+            .expect_read()
+            .withf(move |id| *id == written_id)
+            .times(1)
+            .returning(move |_| Ok(stored.clone()));
+        let active = Arc::new(ActiveEmbeddingModel::new(
+            model(
+                vectorizer("model-v1", vec![9.0]),
+                old_emb_repo,
+                MockVectorSearcher::new(),
+            ),
+            Arc::new(active_chunk_repo),
+        ));
+
+        // Пока идёт обход, DocumentService записывает новый чанк старой моделью
+        let mut mock_chunk_repo = MockChunkRepo::new();
+        mock_chunk_repo.expect_count().returning(|| Ok(0));
+        let writer = active.clone();
+        mock_chunk_repo
+            .expect_list()
+            .times(1)
+            .returning(move |_, _| {
+                let embending = ChunkEmbending {
+                    id: Uuid::new_v4(),
+                    chunk_id: written_id,
+                    model_id: "model-v1".into(),
+                    vec: vec![9.0],
+                    metadata: Default::default(),
+                };
+                futures::executor::block_on(writer.save(&embending)).unwrap();
+                Ok(vec![])
+            });
+        mock_chunk_repo
+            .expect_read_many()
+            .withf(move |ids| ids == [written_id])
+            .times(1)
+            .returning(move |_| Ok(vec![written.clone()]));
+
+        let mut new_emb_repo = MockChunkEmbendingRepo::new();
+        new_emb_repo
+            .expect_save()
+            .withf(move |e| {
+                e.chunk_id == written_id && e.model_id == "model-v2" && e.vec == [0.1, 0.2]
+            })
+            .times(2)
+            .returning(|_| Ok(()));
+        let new_vectorizer = vectorizer("model-v2", vec![0.1, 0.2]);
+        let new_model = model(
+            new_vectorizer.clone(),
+            new_emb_repo,
+            MockVectorSearcher::new(),
+        );
+
+        let mut mock_checkpoint_repo = MockReembeddingCheckpointRepo::new();
+        mock_checkpoint_repo.expect_read().returning(|_| Ok(None));
+        mock_checkpoint_repo.expect_save().returning(|_| Ok(()));
+
+        let service = ReembeddingService::new(
+            10,
+            Arc::new(mock_chunk_repo),
+            new_vectorizer,
+            Arc::new(registry(new_model)),
+            Arc::new(mock_checkpoint_repo),
+            active.clone(),
+            Arc::new(tokio::sync::Semaphore::new(5)),
+        );
+
+        service.run().await.unwrap();
+
+        assert_eq!(active.model_id(), "model-v2");
+        // Эмбеддинг, посчитанный сменённой моделью, пересчитывается новой
+        let stale = ChunkEmbending {
+            id: Uuid::new_v4(),
+            chunk_id: written_id,
+            model_id: "model-v1".into(),
+            vec: vec![9.0],
+            metadata: Default::default(),
+        };
+        active.save(&stale).await.unwrap();
+    }
+}
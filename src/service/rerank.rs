@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::fmt::Error;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::domain::embedding::ScoredChunk;
+use crate::domain::rerank::{RerankCandidate, Reranker};
+use crate::domain::unswer::LLM;
+
+// Переранжирование силами генеративной модели: один запрос на всех кандидатов,
+// модель ставит каждому фрагменту оценку от 0 до 10
+pub struct LlmReranker {
+    llm: Arc<dyn LLM>,
+}
+
+impl LlmReranker {
+    pub fn new(llm: Arc<dyn LLM>) -> Self {
+        Self { llm }
+    }
+}
+
+fn prompt(question: &str, candidates: &[RerankCandidate]) -> String {
+    let mut prompt = format!(
+        "Оцени, насколько каждый фрагмент помогает ответить на вопрос, по шкале от 0 до 10.\n\
+         Ответь строками вида «номер: оценка» без пояснений.\n\nВопрос: {question}\n"
+    );
+    for (i, candidate) in candidates.iter().enumerate() {
+        prompt.push_str(&format!("\n[{}]\n{}\n", i + 1, candidate.text));
+    }
+    prompt
+}
+
+// Строки, которые не удалось разобрать, пропускаются; фрагмент без оценки получает 0
+pub(crate) fn parse_scores(reply: &str) -> HashMap<usize, f32> {
+    reply
+        .lines()
+        .filter_map(|line| {
+            let (number, score) = line.split_once(':')?;
+            let number = number
+                .trim()
+                .trim_matches(|c| c == '[' || c == ']')
+                .parse::<usize>()
+                .ok()?;
+            let score = score.trim().parse::<f32>().ok()?;
+            Some((number, score))
+        })
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl Reranker for LlmReranker {
+    async fn rerank(
+        &self,
+        question: &str,
+        candidates: Vec<RerankCandidate>,
+    ) -> Result<Vec<ScoredChunk>, Error> {
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let reply = self.llm.complete(prompt(question, &candidates)).await?;
+        let scores = parse_scores(&reply);
+
+        // Сортировка устойчивая: при равных оценках сохраняется порядок поиска
+        let mut reranked: Vec<ScoredChunk> = candidates
+            .iter()
+            .enumerate()
+            .map(|(i, c)| ScoredChunk {
+                chunk_id: c.chunk_id,
+                score: scores.get(&(i + 1)).copied().unwrap_or(0.0),
+            })
+            .collect();
+        reranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+        Ok(reranked)
+    }
+}
+
+#[derive(Serialize)]
+struct RerankRequest<'a> {
+    query: &'a str,
+    texts: Vec<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct RerankScore {
+    index: usize,
+    score: f32,
+}
+
+// Кросс-энкодер (например, bge-reranker), развёрнутый локально за
+// text-embeddings-inference: POST /rerank {query, texts} -> [{index, score}]
+pub struct CrossEncoderReranker {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl CrossEncoderReranker {
+    pub fn new(url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+fn to_scored(
+    candidates: &[RerankCandidate],
+    scores: Vec<RerankScore>,
+) -> Result<Vec<ScoredChunk>, Error> {
+    let mut reranked = scores
+        .into_iter()
+        .map(|s| {
+            let candidate = candidates.get(s.index).ok_or(Error)?;
+            Ok(ScoredChunk {
+                chunk_id: candidate.chunk_id,
+                score: s.score,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    reranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok(reranked)
+}
+
+#[async_trait::async_trait]
+impl Reranker for CrossEncoderReranker {
+    async fn rerank(
+        &self,
+        question: &str,
+        candidates: Vec<RerankCandidate>,
+    ) -> Result<Vec<ScoredChunk>, Error> {
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let request = RerankRequest {
+            query: question,
+            texts: candidates.iter().map(|c| c.text.as_str()).collect(),
+        };
+        let scores: Vec<RerankScore> = self
+            .client
+            .post(format!("{}/rerank", self.url))
+            .json(&request)
+            .send()
+            .await
+            .map_err(|_| Error)?
+            .error_for_status()
+            .map_err(|_| Error)?
+            .json()
+            .await
+            .map_err(|_| Error)?;
+
+        to_scored(&candidates, scores)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::unswer::MockLLM;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_llm_scores_reorder_candidates() {
+        let candidates: Vec<RerankCandidate> = (1..=3)
+            .map(|i| RerankCandidate {
+                chunk_id: Uuid::from_u128(i),
+                text: format!("фрагмент {i}"),
+                score: 0.5,
+            })
+            .collect();
+
+        let mut mock_llm = MockLLM::new();
+        mock_llm
+            .expect_complete()
+            .withf(|prompt| {
+                prompt.contains("Вопрос: что такое Rust?") && prompt.contains("[3]\nфрагмент 3")
+            })
+            .returning(|_| Ok("1: 2\n2: нет\n3: 9".into()));
+
+        let reranked = LlmReranker::new(Arc::new(mock_llm))
+            .rerank("что такое Rust?", candidates)
+            .await
+            .unwrap();
+
+        let ids: Vec<u128> = reranked.iter().map(|c| c.chunk_id.as_u128()).collect();
+        assert_eq!(ids, vec![3, 1, 2]);
+        assert_eq!(reranked[2].score, 0.0);
+    }
+
+    #[test]
+    fn test_scores_are_mapped_back_to_chunks() {
+        let candidates: Vec<RerankCandidate> = (0..3)
+            .map(|i| RerankCandidate {
+                chunk_id: Uuid::from_u128(i),
+                text: format!("chunk {i}"),
+                score: 0.5,
+            })
+            .collect();
+        let scores: Vec<RerankScore> = serde_json::from_str(
+            r#"[{"index":2,"score":0.1},{"index":0,"score":0.9},{"index":1,"score":0.4}]"#,
+        )
+        .unwrap();
+
+        let reranked = to_scored(&candidates, scores).unwrap();
+
+        let ids: Vec<u128> = reranked.iter().map(|c| c.chunk_id.as_u128()).collect();
+        assert_eq!(ids, vec![0, 1, 2]);
+        assert!(
+            to_scored(
+                &candidates,
+                vec![RerankScore {
+                    index: 7,
+                    score: 1.0
+                }]
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::domain::embedding::ScoredChunk;
+use crate::domain::lexical::LexicalIndex;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fusion {
+    // Reciprocal rank fusion: score = Σ 1 / (k + rank), k обычно 60
+    ReciprocalRank { k: f32 },
+    // Взвешенная сумма оценок, приведённых min-max к [0, 1];
+    // лексический поиск получает вес 1 - vector_weight
+    Weighted { vector_weight: f32 },
+}
+
+#[derive(Clone, Default)]
+pub enum RetrievalMode {
+    #[default]
+    Vector,
+    Hybrid {
+        lexical_index: Arc<dyn LexicalIndex>,
+        fusion: Fusion,
+    },
+}
+
+fn normalized(found: &[ScoredChunk]) -> Vec<(Uuid, f32)> {
+    let max = found.iter().map(|c| c.score).fold(f32::MIN, f32::max);
+    let min = found.iter().map(|c| c.score).fold(f32::MAX, f32::min);
+    found
+        .iter()
+        .map(|c| {
+            let score = if max > min {
+                (c.score - min) / (max - min)
+            } else {
+                1.0
+            };
+            (c.chunk_id, score)
+        })
+        .collect()
+}
+
+// Объединяет результаты векторного и лексического поиска в один ранжированный список.
+// Оценки результата в шкале слияния (ScoreKind::Fused), а не векторной близости
+pub fn fuse(
+    vector: &[ScoredChunk],
+    lexical: &[ScoredChunk],
+    fusion: Fusion,
+    top_k: usize,
+) -> Vec<ScoredChunk> {
+    let mut scores: HashMap<Uuid, f32> = HashMap::new();
+
+    match fusion {
+        Fusion::ReciprocalRank { k } => {
+            for found in [vector, lexical] {
+                for (rank, c) in found.iter().enumerate() {
+                    *scores.entry(c.chunk_id).or_default() += 1.0 / (k + rank as f32 + 1.0);
+                }
+            }
+        }
+        Fusion::Weighted { vector_weight } => {
+            for (found, weight) in [(vector, vector_weight), (lexical, 1.0 - vector_weight)] {
+                for (chunk_id, score) in normalized(found) {
+                    *scores.entry(chunk_id).or_default() += weight * score;
+                }
+            }
+        }
+    }
+
+    let mut fused: Vec<ScoredChunk> = scores
+        .into_iter()
+        .map(|(chunk_id, score)| ScoredChunk { chunk_id, score })
+        .collect();
+    fused.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(a.chunk_id.cmp(&b.chunk_id))
+    });
+    fused.truncate(top_k);
+    fused
+}
+
+// Объединяет результаты поиска по нескольким формулировкам запроса;
+// чанк, найденный несколько раз, получает лучшую из своих оценок.
+// При равных оценках сохраняется порядок, в котором чанки были найдены.
+pub fn union(found: Vec<Vec<ScoredChunk>>, top_k: usize) -> Vec<ScoredChunk> {
+    let mut positions: HashMap<Uuid, usize> = HashMap::new();
+    let mut united: Vec<ScoredChunk> = Vec::new();
+    for c in found.into_iter().flatten() {
+        match positions.get(&c.chunk_id) {
+            Some(&i) => united[i].score = united[i].score.max(c.score),
+            None => {
+                positions.insert(c.chunk_id, united.len());
+                united.push(c);
+            }
+        }
+    }
+
+    united.sort_by(|a, b| b.score.total_cmp(&a.score));
+    united.truncate(top_k);
+    united
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ids;
+
+    fn scored(ids: &[u128], scores: &[f32]) -> Vec<ScoredChunk> {
+        ids.iter()
+            .zip(scores)
+            .map(|(id, score)| ScoredChunk {
+                chunk_id: Uuid::from_u128(*id),
+                score: *score,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reciprocal_rank_fusion_prefers_chunks_found_by_both() {
+        let vector = scored(&[1, 2, 3], &[0.9, 0.8, 0.7]);
+        let lexical = scored(&[4, 3], &[12.0, 3.0]);
+
+        let fused = fuse(&vector, &lexical, Fusion::ReciprocalRank { k: 60.0 }, 3);
+
+        assert_eq!(ids(&fused), vec![3, 1, 4]);
+    }
+
+    #[test]
+    fn test_weighted_fusion_follows_weights() {
+        let vector = scored(&[1, 2], &[0.9, 0.1]);
+        let lexical = scored(&[2, 1], &[10.0, 1.0]);
+
+        let by_vector = fuse(
+            &vector,
+            &lexical,
+            Fusion::Weighted { vector_weight: 0.8 },
+            2,
+        );
+        let by_lexical = fuse(
+            &vector,
+            &lexical,
+            Fusion::Weighted { vector_weight: 0.2 },
+            2,
+        );
+
+        assert_eq!(ids(&by_vector), vec![1, 2]);
+        assert_eq!(ids(&by_lexical), vec![2, 1]);
+        assert!((by_vector[0].score - 0.8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_union_keeps_best_score() {
+        let original = scored(&[1, 2], &[0.9, 0.5]);
+        let rewritten = scored(&[2, 3], &[0.95, 0.4]);
+
+        let united = union(vec![original, rewritten], 2);
+
+        assert_eq!(ids(&united), vec![2, 1]);
+        assert_eq!(united[0].score, 0.95);
+    }
+}
@@ -1,17 +1,62 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt::Error;
 use std::sync::Arc;
+use std::time::Instant;
 
-use uuid::Uuid;
-
+use futures::StreamExt;
 use futures::future::join_all;
-use tokio::sync::Mutex;
+use futures::stream;
+use uuid::Uuid;
 
 use crate::domain::{
-    document::ChunkRepo,
-    embedding::{QuestionEmbeddingRepo, VectorSearcher},
-    question::QuestionRepo,
-    unswer::{LLM, Unswer, UnswerRepo},
+    conversation::{ConversationRepo, ConversationTurn},
+    document::{Chunk, ChunkRepo, DocumentRepo},
+    embedding::{
+        ChunkEmbendingRepo, QuestionEmbeddingRepo, QuestionEmbending, ScoredChunk, TextVectorizer,
+        VectorSearcher,
+    },
+    filter::Filter,
+    groundedness::ClaimVerifier,
+    prompt::{HistoryTurn, Prompt, PromptTemplateRepo},
+    question::{Question, QuestionRepo},
+    rerank::{RerankCandidate, Reranker},
+    tokenizer::Tokenizer,
+    unswer::{
+        Citation, ContextEntry, Generation, LLM, ScoreKind, SuggestedDocument, TokenStream,
+        TokenUsage, Unswer, UnswerEvent, UnswerRepo, UnswerStream, UnswerTimings,
+    },
 };
+use crate::service::cache::UnswerCache;
+use crate::service::citation::parse_citations;
+use crate::service::context::{ContextAssembler, fit_history};
+use crate::service::groundedness::{Groundedness, check_groundedness};
+use crate::service::mmr::select_mmr;
+use crate::service::neighbours::expand_neighbours;
+use crate::service::parent::to_parent_documents;
+use crate::service::retrieval::{RetrievalMode, fuse, union};
+
+pub struct UnswerRequest {
+    pub question_id: Uuid,
+    pub similar_k: usize,
+    // Чанки с близостью ниже порога не попадают в контекст
+    pub min_score: Option<f32>,
+    // Ограничивает поиск частью корпуса: документом, арендатором, тегом и т.п.
+    pub filter: Option<Filter>,
+    // Шаблон промпта "name@version"; None — шаблон сервиса по умолчанию
+    pub template: Option<String>,
+}
+
+impl UnswerRequest {
+    pub fn new(question_id: Uuid, similar_k: usize) -> Self {
+        Self {
+            question_id,
+            similar_k,
+            min_score: None,
+            filter: None,
+            template: None,
+        }
+    }
+}
 
 pub struct UnswerService {
     llm: Arc<dyn LLM>,
@@ -21,6 +66,224 @@ pub struct UnswerService {
     vector_searcher: Arc<dyn VectorSearcher>,
     chunk_repo: Arc<dyn ChunkRepo>,
     semaphore: Arc<tokio::sync::Semaphore>,
+    retrieval_mode: RetrievalMode,
+    reranker: Option<Arc<dyn Reranker>>,
+    mmr: Option<Mmr>,
+    context_assembler: Option<ContextAssembler>,
+    // Сколько соседних чанков документа добавлять с каждой стороны от найденного
+    neighbours: usize,
+    parent_documents: Option<ParentDocuments>,
+    // Сколько кандидатов достаётся из поиска для переранжирования и MMR
+    candidates: usize,
+    // Сколько кандидатов получает reranker
+    rerank_candidates: usize,
+    // Пересчитывает эмбеддинги вопросов, посчитанные уже сменённой моделью
+    vectorizer: Option<Arc<dyn TextVectorizer>>,
+    prompt_templates: Option<PromptTemplates>,
+    no_answer_policy: Option<NoAnswerPolicy>,
+    verification: Option<Verification>,
+    conversations: Option<Conversations>,
+    cache: Option<UnswerCache>,
+}
+
+// Когда считать, что в корпусе нет ответа, и модель не вызывать
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoAnswerPolicy {
+    // Порог для лучшей векторной близости найденных чанков к вопросу, в шкале Metric.
+    // Оценки после слияния и переранжирования в другой шкале и с порогом не сравниваются
+    pub min_score: f32,
+    // Сколько документов из слабых совпадений предложить вместо ответа
+    pub suggestions: usize,
+}
+
+// Проверка ответа по контексту после генерации
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroundednessPolicy {
+    // Ответ с долей подтверждённых утверждений ниже порога считается необоснованным
+    pub min_score: f32,
+    // Сколько раз заново генерировать необоснованный ответ; остаётся самый обоснованный
+    pub retries: usize,
+    // Необоснованный после всех попыток ответ не отдаётся и не сохраняется
+    pub refuse: bool,
+}
+
+// Фрагмент контекста ответа; number — номер, по которому на него ссылается текст
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct Source {
+    pub number: usize,
+    pub chunk_id: Uuid,
+    pub doc_id: Uuid,
+    pub title: Option<String>,
+    pub score: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Answer {
+    pub unswer_id: Uuid,
+    pub text: String,
+    pub citations: Vec<Citation>,
+    pub sources: Vec<Source>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UnswerResult {
+    Answered(Answer),
+    // Релевантного контекста нет, модель не вызывалась и ответ не сохранён
+    NotFound { suggestions: Vec<SuggestedDocument> },
+    // Ответ не подтверждается контекстом, и политика требует отказа
+    Ungrounded(Groundedness),
+}
+
+struct Prepared {
+    question: Question,
+    context: Vec<ContextEntry>,
+    dropped: Vec<Uuid>,
+    truncated: Option<Uuid>,
+    // В какой шкале оценки контекста
+    score_kind: ScoreKind,
+    // Лучшая векторная близость среди найденных чанков; NEG_INFINITY — ничего не найдено
+    similarity: f32,
+    // Предыдущие ходы разговора, поместившиеся в бюджет
+    history: Vec<HistoryTurn>,
+    // Промпт по шаблону; None — промпт собирает сама модель
+    prompt: Option<Prompt>,
+    // Заполнены этапы до генерации
+    timings: UnswerTimings,
+}
+
+// Всё, что сохраняется вместе с ответом, кроме текста и контекста
+#[derive(Default)]
+struct Draft {
+    question_id: Uuid,
+    similar_k: usize,
+    min_score: Option<f32>,
+    dropped: Vec<Uuid>,
+    truncated: Option<Uuid>,
+    template_id: Option<String>,
+    model_id: String,
+    usage: Option<TokenUsage>,
+    groundedness: Option<Groundedness>,
+    timings: UnswerTimings,
+}
+
+struct StreamState {
+    tokens: TokenStream,
+    text: String,
+    context: Vec<ContextEntry>,
+    draft: Draft,
+    started: Instant,
+    unswer_repo: Arc<dyn UnswerRepo>,
+    score_kind: ScoreKind,
+    verifier: Option<Arc<dyn ClaimVerifier>>,
+    turn: Option<TurnLink>,
+    finished: bool,
+}
+
+// Ход разговора, в который записывается ответ
+struct TurnLink {
+    repo: Arc<dyn ConversationRepo>,
+    conversation_id: Uuid,
+    question: String,
+}
+
+impl TurnLink {
+    async fn record(&self, unswer: &Unswer) -> Result<(), Error> {
+        // Если вопрос сохранён в обход QuestionService::process_follow_up,
+        // ход с ним добавляется в конец разговора
+        let turn = ConversationTurn {
+            question_id: unswer.question_id,
+            question: self.question.clone(),
+            unswer_id: Some(unswer.id),
+            unswer: Some(unswer.text.clone()),
+        };
+        self.repo.update_turn(self.conversation_id, &turn).await
+    }
+}
+
+// Разбирает ссылки в ответе модели и сохраняет ответ вместе с его контекстом
+async fn save_unswer(
+    unswer_repo: &dyn UnswerRepo,
+    score_kind: ScoreKind,
+    text: &str,
+    context: &[ContextEntry],
+    draft: Draft,
+) -> Result<Unswer, Error> {
+    let (text, citations) = parse_citations(text, context);
+
+    let mut unswer = Unswer::new(draft.question_id, text, context, score_kind);
+    unswer.similar_k = draft.similar_k;
+    unswer.min_score = draft.min_score;
+    unswer.dropped_chunks_id = draft.dropped;
+    unswer.truncated_chunk_id = draft.truncated;
+    unswer.citations = citations;
+    unswer.template_id = draft.template_id;
+    if let Some(groundedness) = draft.groundedness {
+        unswer.groundedness = Some(groundedness.score);
+        unswer.unsupported_claims = groundedness.unsupported_claims;
+    }
+    unswer.model_id = draft.model_id;
+    unswer.usage = draft.usage;
+    unswer.timings = draft.timings;
+    unswer_repo.save(&unswer).await?;
+    Ok(unswer)
+}
+
+fn add_usage(total: Option<TokenUsage>, usage: Option<TokenUsage>) -> Option<TokenUsage> {
+    match (total, usage) {
+        (Some(total), Some(usage)) => Some(total + usage),
+        (total, usage) => total.or(usage),
+    }
+}
+
+struct ParentDocuments {
+    document_repo: Arc<dyn DocumentRepo>,
+    max_chars: usize,
+}
+
+struct Mmr {
+    embending_repo: Arc<dyn ChunkEmbendingRepo>,
+    lambda: f32,
+}
+
+struct PromptTemplates {
+    repo: Arc<dyn PromptTemplateRepo>,
+    default_template: String,
+}
+
+struct Conversations {
+    repo: Arc<dyn ConversationRepo>,
+    tokenizer: Arc<dyn Tokenizer>,
+    // Бюджет истории в токенах; поместившаяся история занимает окно наравне с фрагментами
+    max_history_tokens: usize,
+}
+
+struct Verification {
+    verifier: Arc<dyn ClaimVerifier>,
+    policy: GroundednessPolicy,
+}
+
+fn answer(unswer: Unswer, context: &[ContextEntry]) -> Answer {
+    let sources = context
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| Source {
+            number: i + 1,
+            chunk_id: entry.chunk_id,
+            doc_id: entry.doc_id,
+            title: entry.title.clone(),
+            score: entry.score,
+        })
+        .collect();
+    Answer {
+        unswer_id: unswer.id,
+        text: unswer.text,
+        citations: unswer.citations,
+        sources,
+    }
+}
+
+fn passages(context: &[ContextEntry]) -> Vec<String> {
+    context.iter().map(|entry| entry.text.clone()).collect()
 }
 
 impl UnswerService {
@@ -40,21 +303,370 @@ impl UnswerService {
             question_embeding_repo,
             vector_searcher,
             chunk_repo,
-            semaphore: semaphore,
+            semaphore,
+            retrieval_mode: RetrievalMode::default(),
+            reranker: None,
+            mmr: None,
+            context_assembler: None,
+            neighbours: 0,
+            parent_documents: None,
+            candidates: 0,
+            rerank_candidates: 0,
+            vectorizer: None,
+            prompt_templates: None,
+            no_answer_policy: None,
+            verification: None,
+            conversations: None,
+            cache: None,
         }
     }
+
+    // Тот же ActiveEmbeddingModel, что и у поисковика: после смены модели старые
+    // эмбеддинги вопросов несравнимы с чанками и пересчитываются при чтении
+    pub fn with_vectorizer(mut self, vectorizer: Arc<dyn TextVectorizer>) -> Self {
+        self.vectorizer = Some(vectorizer);
+        self
+    }
+
+    pub fn with_retrieval_mode(mut self, retrieval_mode: RetrievalMode) -> Self {
+        self.retrieval_mode = retrieval_mode;
+        self
+    }
+
+    // Поиск возвращает candidates чанков, в контекст попадают similar_k лучших после переранжирования
+    pub fn with_reranker(mut self, reranker: Arc<dyn Reranker>, candidates: usize) -> Self {
+        self.reranker = Some(reranker);
+        self.rerank_candidates = candidates;
+        self.candidates = self.candidates.max(candidates);
+        self
+    }
+
+    // Из candidates найденных чанков выбираются similar_k релевантных и непохожих друг на друга.
+    // Векторы кандидатов читаются из embending_repo той же модели, что и у поисковика.
+    // Если задан и reranker, MMR отбирает столько чанков, сколько он переранжирует,
+    // а similar_k лучших из них выбирает reranker.
+    pub fn with_mmr(
+        mut self,
+        embending_repo: Arc<dyn ChunkEmbendingRepo>,
+        lambda: f32,
+        candidates: usize,
+    ) -> Self {
+        self.mmr = Some(Mmr {
+            embending_repo,
+            lambda,
+        });
+        self.candidates = self.candidates.max(candidates);
+        self
+    }
+
+    // Каждый найденный чанк дополняется window соседями до и после него
+    pub fn with_neighbours(mut self, window: usize) -> Self {
+        self.neighbours = window;
+        self
+    }
+
+    // Документы не длиннее max_chars (или их краткое содержание) попадают в контекст целиком
+    // вместо своих чанков, остальные документы представлены найденными чанками
+    pub fn with_parent_documents(
+        mut self,
+        document_repo: Arc<dyn DocumentRepo>,
+        max_chars: usize,
+    ) -> Self {
+        self.parent_documents = Some(ParentDocuments {
+            document_repo,
+            max_chars,
+        });
+        self
+    }
+
+    // Без сборщика в промпт попадают все найденные чанки
+    pub fn with_context_assembler(mut self, context_assembler: ContextAssembler) -> Self {
+        self.context_assembler = Some(context_assembler);
+        self
+    }
+
+    // Без политики модель отвечает даже по пустому или слабому контексту
+    pub fn with_no_answer_policy(mut self, policy: NoAnswerPolicy) -> Self {
+        self.no_answer_policy = Some(policy);
+        self
+    }
+
+    // Каждое предложение ответа проверяется по фрагментам контекста
+    pub fn with_verifier(
+        mut self,
+        verifier: Arc<dyn ClaimVerifier>,
+        policy: GroundednessPolicy,
+    ) -> Self {
+        self.verification = Some(Verification { verifier, policy });
+        self
+    }
+
+    // Вопросы разговора получают предыдущие ходы в пределах max_history_tokens,
+    // ответ записывается в разговор
+    pub fn with_conversations(
+        mut self,
+        repo: Arc<dyn ConversationRepo>,
+        tokenizer: Arc<dyn Tokenizer>,
+        max_history_tokens: usize,
+    ) -> Self {
+        self.conversations = Some(Conversations {
+            repo,
+            tokenizer,
+            max_history_tokens,
+        });
+        self
+    }
+
+    // Вопрос, близкий к уже отвеченному, получает сохранённый ответ без поиска и генерации.
+    // Вопросы с фильтром и вопросы разговора кэш не используют: их ответ зависит не только
+    // от текста вопроса.
+    pub fn with_cache(mut self, cache: UnswerCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    // Промпт собирается по шаблону из запроса или по default_template ("name@version").
+    // Без шаблонов вопрос и контекст передаются модели как есть.
+    pub fn with_prompt_templates(
+        mut self,
+        repo: Arc<dyn PromptTemplateRepo>,
+        default_template: &str,
+    ) -> Self {
+        self.prompt_templates = Some(PromptTemplates {
+            repo,
+            default_template: default_template.to_string(),
+        });
+        self
+    }
 }
 
 impl UnswerService {
-    pub async fn get_unswer(&self, question_id: Uuid, similar_k: usize) -> Result<String, Error> {
+    pub async fn get_unswer(&self, request: &UnswerRequest) -> Result<UnswerResult, Error> {
+        let (question, embedding) = self.read_question(request.question_id).await?;
+        if let Some((unswer, context)) = self.cached(request, &question, &embedding).await? {
+            return Ok(UnswerResult::Answered(answer(unswer, &context)));
+        }
+
+        let Prepared {
+            question,
+            context,
+            dropped,
+            truncated,
+            score_kind,
+            similarity,
+            history,
+            prompt,
+            mut timings,
+        } = self.prepare(request, question, embedding).await?;
+
+        if let Some(suggestions) = self.no_answer(&context, similarity) {
+            return Ok(UnswerResult::NotFound { suggestions });
+        }
+
+        // Формируем ответ
+        let template_id = prompt.as_ref().map(|prompt| prompt.template_id.clone());
+        let started = Instant::now();
+        let generation = self
+            .generate(&question, &context, &history, prompt.clone())
+            .await?;
+        timings.generation += started.elapsed();
+        let mut usage = generation.usage;
+        let mut unswer_text = generation.text;
+
+        // Проверяем ответ по контексту, при необходимости генерируем заново
+        let mut groundedness = None;
+        if let Some(Verification { verifier, policy }) = &self.verification {
+            let passages = passages(&context);
+            let mut best = check_groundedness(verifier.as_ref(), &unswer_text, &passages).await?;
+            for _ in 0..policy.retries {
+                if best.score >= policy.min_score {
+                    break;
+                }
+                let started = Instant::now();
+                let generation = self
+                    .generate(&question, &context, &history, prompt.clone())
+                    .await?;
+                timings.generation += started.elapsed();
+                usage = add_usage(usage, generation.usage);
+
+                let checked =
+                    check_groundedness(verifier.as_ref(), &generation.text, &passages).await?;
+                if checked.score > best.score {
+                    unswer_text = generation.text;
+                    best = checked;
+                }
+            }
+            if policy.refuse && best.score < policy.min_score {
+                return Ok(UnswerResult::Ungrounded(best));
+            }
+            groundedness = Some(best);
+        }
+
+        // Сохраняем ответ
+        let draft = Draft {
+            question_id: request.question_id,
+            similar_k: request.similar_k,
+            min_score: request.min_score,
+            dropped,
+            truncated,
+            template_id,
+            model_id: self.llm.model_id(),
+            usage,
+            groundedness,
+            timings,
+        };
+        let unswer = save_unswer(
+            self.unswer_repo.as_ref(),
+            score_kind,
+            &unswer_text,
+            &context,
+            draft,
+        )
+        .await?;
+        if let Some(turn) = self.turn_link(&question) {
+            turn.record(&unswer).await?;
+        }
+
+        Ok(UnswerResult::Answered(answer(unswer, &context)))
+    }
+
+    // Текст ответа приходит событиями Delta по мере генерации. Когда модель закончит,
+    // ответ сохраняется и поток завершается событием Done. Если поток бросить
+    // до конца, ответ не сохраняется.
+    pub async fn stream_unswer(&self, request: &UnswerRequest) -> Result<UnswerStream, Error> {
+        let (question, embedding) = self.read_question(request.question_id).await?;
+        // Ответ из кэша отдаётся целиком одним событием Delta
+        if let Some((unswer, _)) = self.cached(request, &question, &embedding).await? {
+            let events = vec![
+                Ok(UnswerEvent::Delta(unswer.text.clone())),
+                Ok(UnswerEvent::Done {
+                    unswer_id: unswer.id,
+                    text: unswer.text,
+                    citations: unswer.citations,
+                    groundedness: unswer.groundedness,
+                }),
+            ];
+            return Ok(Box::pin(stream::iter(events)));
+        }
+
+        let Prepared {
+            question,
+            context,
+            dropped,
+            truncated,
+            score_kind,
+            similarity,
+            history,
+            prompt,
+            timings,
+        } = self.prepare(request, question, embedding).await?;
+
+        if let Some(suggestions) = self.no_answer(&context, similarity) {
+            let event = Ok(UnswerEvent::NotFound { suggestions });
+            return Ok(Box::pin(stream::once(async move { event })));
+        }
+
+        let draft = Draft {
+            question_id: request.question_id,
+            similar_k: request.similar_k,
+            min_score: request.min_score,
+            dropped,
+            truncated,
+            template_id: prompt.as_ref().map(|prompt| prompt.template_id.clone()),
+            model_id: self.llm.model_id(),
+            // Потоковые ответы расход токенов не сообщают
+            usage: None,
+            groundedness: None,
+            timings,
+        };
+        let turn = self.turn_link(&question);
+        let started = Instant::now();
+        let tokens = match prompt {
+            Some(prompt) => self.llm.stream_generate(prompt).await?,
+            None => {
+                self.llm
+                    .stream_unswer(question.text, passages(&context), history)
+                    .await?
+            }
+        };
+
+        let state = StreamState {
+            tokens,
+            text: String::new(),
+            context,
+            draft,
+            started,
+            unswer_repo: self.unswer_repo.clone(),
+            score_kind,
+            verifier: self
+                .verification
+                .as_ref()
+                .map(|verification| verification.verifier.clone()),
+            turn,
+            finished: false,
+        };
+
+        Ok(Box::pin(stream::unfold(state, |mut state| async move {
+            if state.finished {
+                return None;
+            }
+            match state.tokens.next().await {
+                Some(Ok(delta)) => {
+                    state.text.push_str(&delta);
+                    Some((Ok(UnswerEvent::Delta(delta)), state))
+                }
+                Some(Err(err)) => {
+                    state.finished = true;
+                    Some((Err(err), state))
+                }
+                None => {
+                    state.finished = true;
+                    state.draft.timings.generation = state.started.elapsed();
+                    state.draft.groundedness = match &state.verifier {
+                        Some(verifier) => {
+                            let passages = passages(&state.context);
+                            match check_groundedness(verifier.as_ref(), &state.text, &passages)
+                                .await
+                            {
+                                Ok(groundedness) => Some(groundedness),
+                                Err(err) => return Some((Err(err), state)),
+                            }
+                        }
+                        None => None,
+                    };
+                    let saved = save_unswer(
+                        state.unswer_repo.as_ref(),
+                        state.score_kind,
+                        &state.text,
+                        &state.context,
+                        std::mem::take(&mut state.draft),
+                    )
+                    .await;
+                    let recorded = match (saved, &state.turn) {
+                        (Ok(unswer), Some(turn)) => turn.record(&unswer).await.map(|_| unswer),
+                        (saved, _) => saved,
+                    };
+                    let done = recorded.map(|unswer| UnswerEvent::Done {
+                        unswer_id: unswer.id,
+                        text: unswer.text,
+                        citations: unswer.citations,
+                        groundedness: unswer.groundedness,
+                    });
+                    Some((done, state))
+                }
+            }
+        })))
+    }
+}
+
+impl UnswerService {
+    async fn read_question(
+        &self,
+        question_id: Uuid,
+    ) -> Result<(Question, QuestionEmbending), Error> {
         // Клонируем зависимости
         let question_repo = self.question_repo.clone();
         let question_emb_repo = self.question_embeding_repo.clone();
-        let vector_searcher = self.vector_searcher.clone();
-        let chunk_repo = self.chunk_repo.clone();
-        let semaphore = self.semaphore.clone();
-        let llm = self.llm.clone();
-        let unswer_repo = self.unswer_repo.clone();
 
         // Запрашиваем вопрос и эмбеддинг параллельно
         let question_handle = tokio::spawn(async move { question_repo.read(question_id).await });
@@ -64,58 +676,483 @@ impl UnswerService {
 
         let question = question_handle.await.unwrap()?;
         let question_embedding = embedding_handle.await.unwrap()?;
+        let question_embedding = self
+            .refresh(question_embedding, question.search_text())
+            .await?;
+        Ok((question, question_embedding))
+    }
+
+    // Сохраняет копию годного ответа на похожий вопрос; None — кэша нет или промах
+    async fn cached(
+        &self,
+        request: &UnswerRequest,
+        question: &Question,
+        embedding: &QuestionEmbending,
+    ) -> Result<Option<(Unswer, Vec<ContextEntry>)>, Error> {
+        let Some(cache) = &self.cache else {
+            return Ok(None);
+        };
+        if request.filter.is_some() || question.conversation_id.is_some() {
+            return Ok(None);
+        }
+        // Ответ переиспользуется только с тем же шаблоном промпта и параметрами поиска
+        let template_id = match &self.prompt_templates {
+            Some(templates) => Some(
+                request
+                    .template
+                    .as_deref()
+                    .unwrap_or(&templates.default_template),
+            ),
+            // Ошибку о ненастроенных шаблонах вернёт prepare
+            None if request.template.is_some() => return Ok(None),
+            None => None,
+        };
+
+        let Some(cached) = cache
+            .lookup(embedding, template_id, request.similar_k, request.min_score)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let unswer = cached.copy_for(question.id);
+        self.unswer_repo.save(&unswer).await?;
+        Ok(Some((unswer, cached.context)))
+    }
+
+    // Поиск и подготовка контекста: всё, что происходит до обращения к модели
+    async fn prepare(
+        &self,
+        request: &UnswerRequest,
+        question: Question,
+        question_embedding: QuestionEmbending,
+    ) -> Result<Prepared, Error> {
+        let question_id = request.question_id;
+
+        let started = Instant::now();
+        let history = self.history(&question).await?;
+
+        // Переформулировки ищутся вместе с исходным вопросом
+        let mut queries = vec![(
+            question.search_text().to_string(),
+            question_embedding.vec.clone(),
+        )];
+        if !question.rewrites.is_empty() {
+            for embedding in self
+                .question_embeding_repo
+                .read_rewrites(question_id)
+                .await?
+            {
+                if let Some(rewrite) = embedding.rewrite.and_then(|i| question.rewrites.get(i)) {
+                    let embedding = self.refresh(embedding, &rewrite.text).await?;
+                    queries.push((rewrite.text.clone(), embedding.vec));
+                }
+            }
+        }
 
         // Ищем похожие чанки
-        let k_nearest = vector_searcher
-            .search_similar(&question_embedding.vec, similar_k)
-            .await?;
+        let found = join_all(
+            queries
+                .iter()
+                .map(|(text, vector)| self.retrieve(text, vector, request)),
+        )
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, Error>>()?;
+        let (found, similarities): (Vec<_>, Vec<_>) = found.into_iter().unzip();
+        let similarity = similarities.into_iter().fold(f32::NEG_INFINITY, f32::max);
+        let k_nearest = union(found, self.candidates.max(request.similar_k));
+        let score_kind = match self.retrieval_mode {
+            RetrievalMode::Vector => ScoreKind::Similarity(self.vector_searcher.metric()),
+            RetrievalMode::Hybrid { .. } => ScoreKind::Fused,
+        };
+
+        let k_nearest = match &self.mmr {
+            Some(mmr) => {
+                let top_k = match self.reranker {
+                    Some(_) => self.rerank_candidates.max(request.similar_k),
+                    None => request.similar_k,
+                };
+                self.diversify(mmr, &question_embedding.vec, k_nearest, top_k)
+                    .await
+            }
+            None => k_nearest,
+        };
+
+        // Загружаем чанки, сохраняя порядок ранжирования
+        let context = self.load_context(k_nearest).await?;
+
+        let reranking = Instant::now();
+        let (context, score_kind) = match &self.reranker {
+            Some(reranker) => (
+                Self::rerank(reranker.as_ref(), &question, context, request.similar_k).await?,
+                ScoreKind::Reranked,
+            ),
+            None => (context, score_kind),
+        };
+        let rerank = reranking.elapsed();
+
+        let context = match self.neighbours {
+            0 => context,
+            window => self.expand(context, window).await?,
+        };
+
+        let context = match &self.parent_documents {
+            Some(parents) => self.to_parents(parents, context).await?,
+            None => context,
+        };
+
+        // Оставляем то, что помещается в окно контекста модели
+        let (context, dropped, truncated) = match &self.context_assembler {
+            Some(assembler) => {
+                let assembled = assembler.assemble(&question.text, &history, context);
+                (assembled.entries, assembled.dropped, assembled.truncated)
+            }
+            None => (context, Vec::new(), None),
+        };
+        let timings = UnswerTimings {
+            retrieval: started.elapsed() - rerank,
+            rerank,
+            generation: Default::default(),
+        };
+
+        let prompt = match &self.prompt_templates {
+            Some(templates) => {
+                let template_id = request
+                    .template
+                    .as_deref()
+                    .unwrap_or(&templates.default_template);
+                let template = templates.repo.read(template_id).await?;
+                Some(template.render(&question.text, &context, &history))
+            }
+            // Шаблон запрошен, но сервис настроен без них
+            None if request.template.is_some() => return Err(Error),
+            None => None,
+        };
+
+        Ok(Prepared {
+            question,
+            context,
+            dropped,
+            truncated,
+            score_kind,
+            similarity,
+            history,
+            prompt,
+            timings,
+        })
+    }
+
+    // Эмбеддинг, посчитанный не текущей моделью, векторизуется заново
+    async fn refresh(
+        &self,
+        embedding: QuestionEmbending,
+        text: &str,
+    ) -> Result<QuestionEmbending, Error> {
+        let Some(vectorizer) = &self.vectorizer else {
+            return Ok(embedding);
+        };
+        let model_id = vectorizer.model_id();
+        if embedding.model_id == model_id {
+            return Ok(embedding);
+        }
+        Ok(QuestionEmbending {
+            vec: vectorizer.vectorize(text).await?,
+            model_id,
+            ..embedding
+        })
+    }
+}
+
+impl UnswerService {
+    async fn history(&self, question: &Question) -> Result<Vec<HistoryTurn>, Error> {
+        let (Some(conversations), Some(conversation_id)) =
+            (&self.conversations, question.conversation_id)
+        else {
+            return Ok(Vec::new());
+        };
+        let conversation = conversations.repo.read(conversation_id).await?;
+        Ok(fit_history(
+            conversations.tokenizer.as_ref(),
+            conversation.history(question.id),
+            conversations.max_history_tokens,
+        ))
+    }
+
+    fn turn_link(&self, question: &Question) -> Option<TurnLink> {
+        Some(TurnLink {
+            repo: self.conversations.as_ref()?.repo.clone(),
+            conversation_id: question.conversation_id?,
+            question: question.text.clone(),
+        })
+    }
+
+    async fn generate(
+        &self,
+        question: &Question,
+        context: &[ContextEntry],
+        history: &[HistoryTurn],
+        prompt: Option<Prompt>,
+    ) -> Result<Generation, Error> {
+        match prompt {
+            Some(prompt) => self.llm.generate(prompt).await,
+            None => {
+                self.llm
+                    .formulate_unswer(question.text.clone(), passages(context), history.to_vec())
+                    .await
+            }
+        }
+    }
+
+    // Some — ответа нет: контекст пуст или векторная близость лучшего чанка ниже порога.
+    // Предлагаются документы слабых совпадений, по одному на документ, в порядке контекста.
+    fn no_answer(
+        &self,
+        context: &[ContextEntry],
+        similarity: f32,
+    ) -> Option<Vec<SuggestedDocument>> {
+        // Без контекста модель не вызывается, даже если политика не задана
+        if context.is_empty() {
+            return Some(Vec::new());
+        }
+        let policy = self.no_answer_policy.as_ref()?;
+        if similarity >= policy.min_score {
+            return None;
+        }
+
+        let mut seen = HashSet::new();
+        Some(
+            context
+                .iter()
+                .filter(|entry| seen.insert(entry.doc_id))
+                .take(policy.suggestions)
+                .map(|entry| SuggestedDocument {
+                    doc_id: entry.doc_id,
+                    title: entry.title.clone(),
+                    score: entry.score,
+                })
+                .collect(),
+        )
+    }
+
+    async fn retrieve(
+        &self,
+        text: &str,
+        vector: &[f32],
+        request: &UnswerRequest,
+    ) -> Result<(Vec<ScoredChunk>, f32), Error> {
+        let filter = request.filter.as_ref();
+        let top_k = self.candidates.max(request.similar_k);
+        let vector_search =
+            self.vector_searcher
+                .search_similar(vector, top_k, request.min_score, filter);
+
+        // Вместе с найденным возвращается лучшая векторная близость для NoAnswerPolicy
+        let best = |found: &[ScoredChunk]| {
+            found
+                .iter()
+                .map(|c| c.score)
+                .fold(f32::NEG_INFINITY, f32::max)
+        };
+        match &self.retrieval_mode {
+            RetrievalMode::Vector => {
+                let found = vector_search.await?;
+                let similarity = best(&found);
+                Ok((found, similarity))
+            }
+            // min_score относится к векторной близости, в Unswer попадут оценки после слияния
+            RetrievalMode::Hybrid {
+                lexical_index,
+                fusion,
+            } => {
+                let lexical_search = lexical_index.search_text(text, top_k, filter);
+                let (by_vector, by_text) = tokio::join!(vector_search, lexical_search);
+                let by_vector = by_vector?;
+                Ok((
+                    fuse(&by_vector, &by_text?, *fusion, top_k),
+                    best(&by_vector),
+                ))
+            }
+        }
+    }
+
+    // Читает найденные чанки одним запросом; порядок записей совпадает с порядком found
+    async fn load_context(&self, found: Vec<ScoredChunk>) -> Result<Vec<ContextEntry>, Error> {
+        let chunk_ids: Vec<Uuid> = found.iter().map(|c| c.chunk_id).collect();
+        let mut chunks: HashMap<Uuid, Chunk> = {
+            let _permit = self.semaphore.acquire().await.unwrap();
+            self.chunk_repo.read_many(&chunk_ids).await?
+        }
+        .into_iter()
+        .map(|chunk| (chunk.id, chunk))
+        .collect();
+
+        // Чанк мог быть удалён между поиском и чтением, такой чанк пропускаем
+        Ok(found
+            .into_iter()
+            .filter_map(|scored| {
+                let chunk = chunks.remove(&scored.chunk_id)?;
+                Some(ContextEntry::from_chunk(chunk, scored.score))
+            })
+            .collect())
+    }
 
-        // Загружаем чанки параллельно
-        let context = Arc::new(Mutex::new(Vec::<String>::new()));
-        let mut chunk_handles = Vec::with_capacity(k_nearest.len());
+    async fn diversify(
+        &self,
+        mmr: &Mmr,
+        query: &[f32],
+        found: Vec<ScoredChunk>,
+        similar_k: usize,
+    ) -> Vec<ScoredChunk> {
+        let mut handles = Vec::with_capacity(found.len());
 
-        for chunk_id in k_nearest.clone() {
-            let chunk_repo = chunk_repo.clone();
-            let semaphore = semaphore.clone();
-            let context = context.clone();
+        for scored in found {
+            let embending_repo = mmr.embending_repo.clone();
+            let semaphore = self.semaphore.clone();
 
-            chunk_handles.push(tokio::spawn(async move {
+            handles.push(tokio::spawn(async move {
                 let _permit = semaphore.acquire().await.unwrap();
-                let chunk = chunk_repo.read(chunk_id).await?;
-                context.lock().await.push(chunk.text);
-                Ok::<(), Error>(())
+                let embending = embending_repo.read(scored.chunk_id).await?;
+                Ok::<_, Error>((scored, embending.vec))
             }));
         }
 
-        // Дожидаемся всех чанков
-        join_all(chunk_handles)
+        // Кандидат без эмбеддинга (например, ещё не переиндексированный) пропускается,
+        // остальные сохраняют порядок ранжирования
+        let candidates: Vec<_> = join_all(handles)
             .await
             .into_iter()
-            .collect::<Result<Vec<_>, _>>()
-            .unwrap();
+            .filter_map(|h| h.unwrap().ok())
+            .collect();
 
-        let context = context.lock().await.clone();
+        select_mmr(
+            query,
+            &candidates,
+            self.vector_searcher.metric(),
+            mmr.lambda,
+            similar_k,
+        )
+    }
 
-        // Формируем ответ
-        let unswer_text = llm.formulate_unswer(question.text, context).await?;
+    async fn expand(
+        &self,
+        context: Vec<ContextEntry>,
+        window: usize,
+    ) -> Result<Vec<ContextEntry>, Error> {
+        let doc_ids: HashSet<Uuid> = context.iter().map(|entry| entry.doc_id).collect();
+        let mut handles = Vec::with_capacity(doc_ids.len());
 
-        // Сохраняем ответ
-        let unswer = Unswer::new(unswer_text.clone(), k_nearest);
-        unswer_repo.save(&unswer).await?;
+        for doc_id in doc_ids {
+            let chunk_repo = self.chunk_repo.clone();
+            let semaphore = self.semaphore.clone();
+
+            handles.push(tokio::spawn(async move {
+                let _permit = semaphore.acquire().await.unwrap();
+                let mut chunks = chunk_repo.read_by_doc(doc_id).await?;
+                chunks.sort_by_key(|c| c.position);
+                Ok::<_, Error>((doc_id, chunks))
+            }));
+        }
 
-        Ok(unswer_text)
+        let documents = join_all(handles)
+            .await
+            .into_iter()
+            .map(|h| h.unwrap())
+            .collect::<Result<HashMap<_, _>, Error>>()?;
+
+        Ok(expand_neighbours(context, &documents, window))
     }
-}
 
+    async fn to_parents(
+        &self,
+        parents: &ParentDocuments,
+        context: Vec<ContextEntry>,
+    ) -> Result<Vec<ContextEntry>, Error> {
+        let doc_ids: HashSet<Uuid> = context.iter().map(|entry| entry.doc_id).collect();
+        let mut handles = Vec::with_capacity(doc_ids.len());
+
+        for doc_id in doc_ids {
+            let document_repo = parents.document_repo.clone();
+            let semaphore = self.semaphore.clone();
+
+            handles.push(tokio::spawn(async move {
+                let _permit = semaphore.acquire().await.unwrap();
+                let document = document_repo.read(doc_id).await?;
+                Ok::<_, Error>((doc_id, document))
+            }));
+        }
+
+        let documents = join_all(handles)
+            .await
+            .into_iter()
+            .map(|h| h.unwrap())
+            .collect::<Result<HashMap<_, _>, Error>>()?;
+
+        Ok(to_parent_documents(context, &documents, parents.max_chars))
+    }
+
+    // Переоценивает найденные чанки и оставляет similar_k лучших с оценками реранкера
+    async fn rerank(
+        reranker: &dyn Reranker,
+        question: &Question,
+        context: Vec<ContextEntry>,
+        similar_k: usize,
+    ) -> Result<Vec<ContextEntry>, Error> {
+        let candidates = context
+            .iter()
+            .map(|entry| RerankCandidate {
+                chunk_id: entry.chunk_id,
+                text: entry.text.clone(),
+                score: entry.score,
+            })
+            .collect();
+
+        let mut entries: HashMap<Uuid, ContextEntry> = context
+            .into_iter()
+            .map(|entry| (entry.chunk_id, entry))
+            .collect();
+        let mut reranked = reranker.rerank(question.search_text(), candidates).await?;
+        reranked.truncate(similar_k);
+        Ok(reranked
+            .into_iter()
+            .filter_map(|c| {
+                let entry = entries.remove(&c.chunk_id)?;
+                Some(ContextEntry {
+                    score: c.score,
+                    ..entry
+                })
+            })
+            .collect())
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::question::{MockQuestionRepo, Question};
-    use crate::domain::embedding::{MockQuestionEmbeddingRepo, QuestionEmbending, MockVectorSearcher};
-    use crate::domain::document::{MockChunkRepo, Chunk};
+    use crate::adapter::tokenizer::ApproxTokenizer;
+    use crate::domain::conversation::{Conversation, MockConversationRepo};
+    use crate::domain::document::{Chunk, Document, MockChunkRepo, MockDocumentRepo};
+    use crate::domain::embedding::{
+        ChunkEmbending, Metric, MockChunkEmbendingRepo, MockQuestionEmbeddingRepo,
+        MockTextVectorizer, MockVectorSearcher, QuestionEmbending, ScoredChunk, ScoredQuestion,
+    };
+    use crate::domain::filter::TITLE_FIELD;
+    use crate::domain::groundedness::MockClaimVerifier;
+    use crate::domain::lexical::MockLexicalIndex;
+    use crate::domain::prompt::{MockPromptTemplateRepo, PromptTemplate};
+    use crate::domain::question::{MockQuestionRepo, QueryRewrite, Question, RewriteKind};
+    use crate::domain::rerank::MockReranker;
+    use crate::domain::tokenizer::MockTokenizer;
     use crate::domain::unswer::{MockLLM, MockUnswerRepo};
+    use crate::service::context::ContextBudget;
+    use crate::service::retrieval::Fusion;
+    use crate::testing::UnswerMocks;
 
+    fn answer_text(result: UnswerResult) -> String {
+        let UnswerResult::Answered(answer) = result else {
+            panic!("ожидался ответ");
+        };
+        answer.text
+    }
 
     #[tokio::test]
     async fn test_get_unswer_happy_path() {
@@ -132,30 +1169,47 @@ mod tests {
             .returning(move |_| Ok(Question::new("What is Rust?".into())));
 
         let mut mock_embedding_repo = MockQuestionEmbeddingRepo::new();
-        mock_embedding_repo
-            .expect_read()
-            .returning(move |_| Ok(QuestionEmbending{id: Uuid::new_v4(), question_id: question_id.clone(), vec: vec![0.1, 0.2] }));
+        mock_embedding_repo.expect_read().returning(move |_| {
+            Ok(QuestionEmbending {
+                id: Uuid::new_v4(),
+                question_id,
+                rewrite: None,
+                model_id: "test-model".into(),
+                vec: vec![0.1, 0.2],
+            })
+        });
 
         let mut mock_vector_searcher = MockVectorSearcher::new();
+        mock_vector_searcher
+            .expect_metric()
+            .returning(|| crate::domain::embedding::Metric::Cosine);
         mock_vector_searcher
             .expect_search_similar()
-            .returning(move |_, _| Ok(vec![chunk_id]));
+            .returning(move |_, _, _, _| {
+                Ok(vec![ScoredChunk {
+                    chunk_id,
+                    score: 0.9,
+                }])
+            });
 
         let mut mock_chunk_repo = MockChunkRepo::new();
-        mock_chunk_repo
-            .expect_read()
-            .returning(move |_| Ok(Chunk::new(doc_id.clone(), "Rust is a programming language.".into())));
+        mock_chunk_repo.expect_read_many().returning(move |ids| {
+            let mut chunk = Chunk::new(doc_id, "Rust is a programming language.".into());
+            chunk.id = ids[0];
+            Ok(vec![chunk])
+        });
 
         let mut mock_llm = MockLLM::new();
+        mock_llm
+            .expect_model_id()
+            .return_const("test-model".to_string());
         let resp_clone = response_text.clone();
         mock_llm
             .expect_formulate_unswer()
-            .returning(move |_, _| Ok(resp_clone.clone()));
+            .returning(move |_, _, _| Ok(resp_clone.clone().into()));
 
         let mut mock_unswer_repo = MockUnswerRepo::new();
-        mock_unswer_repo
-            .expect_save()
-            .returning(|_| Ok(()));
+        mock_unswer_repo.expect_save().returning(|_| Ok(()));
 
         // Сервис (условный конструктор)
         let service = UnswerService {
@@ -166,14 +1220,1164 @@ mod tests {
             semaphore: Arc::new(tokio::sync::Semaphore::new(5)),
             llm: Arc::new(mock_llm),
             unswer_repo: Arc::new(mock_unswer_repo),
+            retrieval_mode: RetrievalMode::Vector,
+            reranker: None,
+            mmr: None,
+            context_assembler: None,
+            neighbours: 0,
+            parent_documents: None,
+            candidates: 0,
+            rerank_candidates: 0,
+            vectorizer: None,
+            prompt_templates: None,
+            no_answer_policy: None,
+            verification: None,
+            conversations: None,
+            cache: None,
         };
 
         // Вызов
-        let result = service.get_unswer(question_id, 1).await.unwrap();
+        let result = service
+            .get_unswer(&UnswerRequest::new(question_id, 1))
+            .await
+            .unwrap();
 
         // Проверка
-        assert_eq!(result, response_text);
+        assert_eq!(answer_text(result), response_text);
+    }
+
+    fn found(texts: &[&str], scores: &[f32]) -> Vec<(Chunk, f32)> {
+        let doc_id = Uuid::new_v4();
+        texts
+            .iter()
+            .zip(scores)
+            .map(|(text, score)| (Chunk::new(doc_id, text.to_string()), *score))
+            .collect()
+    }
+
+    fn scored(found: &[(Chunk, f32)]) -> Vec<ScoredChunk> {
+        found
+            .iter()
+            .map(|(chunk, score)| ScoredChunk {
+                chunk_id: chunk.id,
+                score: *score,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_get_unswer_passes_min_score_and_filter_to_search() {
+        let found = found(&["Rust — язык программирования."], &[0.9]);
+        let doc_id = found[0].0.doc_id;
+        let mut mocks = UnswerMocks::new("Что такое Rust?", found.clone(), "Язык");
+
+        let scored = scored(&found);
+        mocks
+            .vector_searcher
+            .expect_search_similar()
+            .withf(move |_, k, min_score, filter| {
+                *k == 1 && *min_score == Some(0.5) && *filter == Some(&Filter::document(doc_id))
+            })
+            .times(1)
+            .returning(move |_, _, _, _| Ok(scored.clone()));
+
+        let question_id = mocks.question_id();
+        let mut request = UnswerRequest::new(question_id, 1);
+        request.min_score = Some(0.5);
+        request.filter = Some(Filter::document(doc_id));
+        let result = mocks.service().get_unswer(&request).await.unwrap();
+
+        assert_eq!(answer_text(result), "Язык");
+    }
+
+    #[tokio::test]
+    async fn test_get_unswer_saves_context_scores() {
+        let found = found(&["первый", "второй"], &[0.9, 0.7]);
+        let expected_ids: Vec<Uuid> = found.iter().map(|(chunk, _)| chunk.id).collect();
+        let mut mocks = UnswerMocks::new("Какой?", found, "Первый");
+
+        // Оценки сохраняются вместе с фрагментами контекста, в том же порядке
+        mocks
+            .unswer_repo
+            .expect_save()
+            .withf(move |u| {
+                u.context_chunks_id == expected_ids
+                    && u.context_scores == vec![0.9, 0.7]
+                    && u.score_kind == ScoreKind::Similarity(Metric::Cosine)
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let question_id = mocks.question_id();
+        mocks
+            .service()
+            .get_unswer(&UnswerRequest::new(question_id, 2))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_unswer_hybrid_retrieval() {
+        let found = found(&["Ошибка E1042", "Ошибка E1042"], &[0.9, 0.8]);
+        let (by_vector, by_both) = (found[0].0.id, found[1].0.id);
+        let mut mocks = UnswerMocks::new("Что значит E1042?", found, "Ошибка сохранения");
+
+        let mut mock_lexical_index = MockLexicalIndex::new();
+        mock_lexical_index
+            .expect_search_text()
+            .withf(|text, k, _| text == "Что значит E1042?" && *k == 2)
+            .returning(move |_, _, _| {
+                Ok(vec![ScoredChunk {
+                    chunk_id: by_both,
+                    score: 7.5,
+                }])
+            });
+
+        mocks
+            .unswer_repo
+            .expect_save()
+            .withf(move |u| {
+                u.context_chunks_id == vec![by_both, by_vector] && u.score_kind == ScoreKind::Fused
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let question_id = mocks.question_id();
+        let service = mocks.service().with_retrieval_mode(RetrievalMode::Hybrid {
+            lexical_index: Arc::new(mock_lexical_index),
+            fusion: Fusion::ReciprocalRank { k: 60.0 },
+        });
+
+        let result = service
+            .get_unswer(&UnswerRequest::new(question_id, 2))
+            .await
+            .unwrap();
+
+        assert_eq!(answer_text(result), "Ошибка сохранения");
+    }
+
+    #[tokio::test]
+    async fn test_get_unswer_revectorizes_question_of_switched_model() {
+        let found = found(&["Rust — язык программирования."], &[0.9]);
+        let mut mocks = UnswerMocks::new("Что такое Rust?", found.clone(), "Язык");
+        // Эмбеддинг вопроса сохранён моделью test-model, текущая уже model-v2
+        let mut mock_vectorizer = MockTextVectorizer::new();
+        mock_vectorizer
+            .expect_model_id()
+            .return_const("model-v2".to_string());
+        mock_vectorizer
+            .expect_vectorize()
+            .withf(|text| text == "Что такое Rust?")
+            .times(1)
+            .returning(|_| Ok(vec![0.5, 0.5]));
+
+        let scored = scored(&found);
+        mocks
+            .vector_searcher
+            .expect_search_similar()
+            .withf(|vector, _, _, _| vector == [0.5, 0.5])
+            .times(1)
+            .returning(move |_, _, _, _| Ok(scored.clone()));
+
+        let question_id = mocks.question_id();
+        let result = mocks
+            .service()
+            .with_vectorizer(Arc::new(mock_vectorizer))
+            .get_unswer(&UnswerRequest::new(question_id, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(answer_text(result), "Язык");
+    }
+
+    #[tokio::test]
+    async fn test_get_unswer_reranks_overfetched_candidates() {
+        let found = found(&["первый", "второй", "третий"], &[0.9, 0.8, 0.7]);
+        let (second, third) = (found[1].0.id, found[2].0.id);
+        let mut mocks = UnswerMocks::new("Какой?", found.clone(), "Третий");
+
+        let scored = scored(&found);
+        mocks
+            .vector_searcher
+            .expect_search_similar()
+            .withf(|_, k, _, _| *k == 3)
+            .times(1)
+            .returning(move |_, _, _, _| Ok(scored.clone()));
+
+        let mut mock_reranker = MockReranker::new();
+        mock_reranker
+            .expect_rerank()
+            .withf(|question, candidates| question == "Какой?" && candidates.len() == 3)
+            .returning(move |_, _| {
+                Ok(vec![
+                    ScoredChunk {
+                        chunk_id: third,
+                        score: 5.0,
+                    },
+                    ScoredChunk {
+                        chunk_id: second,
+                        score: 1.0,
+                    },
+                    ScoredChunk {
+                        chunk_id: Uuid::nil(),
+                        score: 0.0,
+                    },
+                ])
+            });
+
+        mocks
+            .llm
+            .expect_formulate_unswer()
+            .withf(|_, context, _| *context == ["третий", "второй"])
+            .times(1)
+            .returning(|_, _, _| Ok("Третий".into()));
+        mocks
+            .unswer_repo
+            .expect_save()
+            .withf(move |u| {
+                u.context_chunks_id == vec![third, second]
+                    && u.context_scores == vec![5.0, 1.0]
+                    && u.score_kind == ScoreKind::Reranked
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let question_id = mocks.question_id();
+        let service = mocks.service().with_reranker(Arc::new(mock_reranker), 3);
+
+        let result = service
+            .get_unswer(&UnswerRequest::new(question_id, 2))
+            .await
+            .unwrap();
+
+        assert_eq!(answer_text(result), "Третий");
+    }
+
+    #[tokio::test]
+    async fn test_context_follows_retrieval_order() {
+        // Поиск возвращает чанки в обратном порядке их создания
+        let mut found = found(&["первый", "второй", "третий"], &[0.7, 0.8, 0.9]);
+        found.reverse();
+        let expected_ids: Vec<Uuid> = found.iter().map(|(chunk, _)| chunk.id).collect();
+        let mut mocks = UnswerMocks::new(
+            "Какой?",
+            found.clone(),
+            "Третий [1]. Второй [2, 7]. Лишний [9].",
+        );
+
+        // Весь контекст читается одним запросом
+        let chunks: Vec<Chunk> = found.into_iter().rev().map(|(chunk, _)| chunk).collect();
+        mocks
+            .chunk_repo
+            .expect_read_many()
+            .times(1)
+            .returning(move |ids| {
+                Ok(chunks
+                    .iter()
+                    .filter(|c| ids.contains(&c.id))
+                    .cloned()
+                    .collect())
+            });
+
+        mocks
+            .llm
+            .expect_formulate_unswer()
+            .withf(|_, context, _| *context == ["третий", "второй", "первый"])
+            .times(1)
+            .returning(|_, _, _| Ok("Третий [1]. Второй [2, 7]. Лишний [9].".into()));
+        mocks
+            .unswer_repo
+            .expect_save()
+            .withf(move |u| {
+                u.context_chunks_id == expected_ids
+                    && u.context_scores == vec![0.9, 0.8, 0.7]
+                    && u.citations
+                        .iter()
+                        .map(|c| (c.number, c.chunk_id))
+                        .collect::<Vec<_>>()
+                        == vec![(1, expected_ids[0]), (2, expected_ids[1])]
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let question_id = mocks.question_id();
+        let result = mocks
+            .service()
+            .get_unswer(&UnswerRequest::new(question_id, 3))
+            .await
+            .unwrap();
+
+        assert_eq!(answer_text(result), "Третий [1]. Второй [2]. Лишний.");
+    }
+
+    #[tokio::test]
+    async fn test_get_unswer_skips_near_duplicates_with_mmr() {
+        let found = found(
+            &["абзац", "тот же абзац", "другой абзац"],
+            &[0.99, 0.98, 0.9],
+        );
+        let chunk_ids: Vec<Uuid> = found.iter().map(|(chunk, _)| chunk.id).collect();
+        let expected_ids = vec![chunk_ids[0], chunk_ids[2]];
+        let mut mocks = UnswerMocks::new("Какой?", found, "Ответ");
+        mocks.vector = vec![1.0, 0.5];
+
+        let vectors = [vec![1.0, 0.4], vec![1.0, 0.38], vec![0.6, 0.8]];
+        let mut mock_chunk_embending_repo = MockChunkEmbendingRepo::new();
+        mock_chunk_embending_repo
+            .expect_read()
+            .times(3)
+            .returning(move |chunk_id| {
+                let i = chunk_ids.iter().position(|id| *id == chunk_id).unwrap();
+                Ok(ChunkEmbending {
+                    id: Uuid::new_v4(),
+                    chunk_id,
+                    model_id: "test".into(),
+                    vec: vectors[i].clone(),
+                    metadata: Default::default(),
+                })
+            });
+
+        mocks
+            .llm
+            .expect_formulate_unswer()
+            .withf(|_, context, _| *context == ["абзац", "другой абзац"])
+            .times(1)
+            .returning(|_, _, _| Ok("Ответ".into()));
+        mocks
+            .unswer_repo
+            .expect_save()
+            .withf(move |u| u.context_chunks_id == expected_ids)
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let question_id = mocks.question_id();
+        let service = mocks
+            .service()
+            .with_mmr(Arc::new(mock_chunk_embending_repo), 0.5, 3);
+
+        service
+            .get_unswer(&UnswerRequest::new(question_id, 2))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mmr_keeps_candidates_for_reranker() {
+        let question_id = Uuid::new_v4();
+        let doc_id = Uuid::new_v4();
+        let chunks: Vec<Chunk> = ["абзац", "тот же абзац", "другой абзац"]
+            .into_iter()
+            .map(|text| Chunk::new(doc_id, text.into()))
+            .collect();
+        let vectors = [vec![1.0, 0.4], vec![1.0, 0.38], vec![0.6, 0.8]];
+        let found: Vec<ScoredChunk> = chunks
+            .iter()
+            .zip([0.99, 0.98, 0.9])
+            .map(|(chunk, score)| ScoredChunk {
+                chunk_id: chunk.id,
+                score,
+            })
+            .collect();
+        let chunk_ids: Vec<Uuid> = chunks.iter().map(|c| c.id).collect();
+        let (first, other) = (chunk_ids[0], chunk_ids[2]);
+
+        let mut mock_question_repo = MockQuestionRepo::new();
+        mock_question_repo
+            .expect_read()
+            .returning(|_| Ok(Question::new("Какой?".into())));
+
+        let mut mock_embedding_repo = MockQuestionEmbeddingRepo::new();
+        mock_embedding_repo.expect_read().returning(move |_| {
+            Ok(QuestionEmbending {
+                id: Uuid::new_v4(),
+                question_id,
+                rewrite: None,
+                model_id: "test-model".into(),
+                vec: vec![1.0, 0.5],
+            })
+        });
+
+        let mut mock_vector_searcher = MockVectorSearcher::new();
+        mock_vector_searcher
+            .expect_metric()
+            .returning(|| Metric::Cosine);
+        mock_vector_searcher
+            .expect_search_similar()
+            .returning(move |_, _, _, _| Ok(found.clone()));
+
+        let mut mock_chunk_embending_repo = MockChunkEmbendingRepo::new();
+        mock_chunk_embending_repo
+            .expect_read()
+            .returning(move |chunk_id| {
+                let i = chunk_ids.iter().position(|id| *id == chunk_id).unwrap();
+                Ok(ChunkEmbending {
+                    id: Uuid::new_v4(),
+                    chunk_id,
+                    model_id: "test".into(),
+                    vec: vectors[i].clone(),
+                    metadata: Default::default(),
+                })
+            });
+
+        let mut mock_chunk_repo = MockChunkRepo::new();
+        mock_chunk_repo.expect_read_many().returning(move |ids| {
+            Ok(chunks
+                .iter()
+                .filter(|c| ids.contains(&c.id))
+                .cloned()
+                .collect())
+        });
+
+        // MMR отбирает два непохожих чанка, из них reranker выбирает один
+        let mut mock_reranker = MockReranker::new();
+        mock_reranker
+            .expect_rerank()
+            .withf(move |_, candidates| {
+                candidates.iter().map(|c| c.chunk_id).collect::<Vec<_>>() == [first, other]
+            })
+            .times(1)
+            .returning(move |_, _| {
+                Ok(vec![ScoredChunk {
+                    chunk_id: other,
+                    score: 3.0,
+                }])
+            });
+
+        let mut mock_llm = MockLLM::new();
+        mock_llm
+            .expect_model_id()
+            .return_const("test-model".to_string());
+        mock_llm
+            .expect_formulate_unswer()
+            .returning(|_, _, _| Ok("Ответ".into()));
+
+        let mut mock_unswer_repo = MockUnswerRepo::new();
+        mock_unswer_repo
+            .expect_save()
+            .withf(move |u| u.context_chunks_id == [other])
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let service = UnswerService::new(
+            Arc::new(mock_llm),
+            Arc::new(mock_unswer_repo),
+            Arc::new(mock_question_repo),
+            Arc::new(mock_embedding_repo),
+            Arc::new(mock_vector_searcher),
+            Arc::new(mock_chunk_repo),
+            Arc::new(tokio::sync::Semaphore::new(5)),
+        )
+        .with_mmr(Arc::new(mock_chunk_embending_repo), 0.5, 3)
+        .with_reranker(Arc::new(mock_reranker), 2);
+
+        service
+            .get_unswer(&UnswerRequest::new(question_id, 1))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mmr_skips_chunks_without_embedding() {
+        let question_id = Uuid::new_v4();
+        let doc_id = Uuid::new_v4();
+        let chunks: Vec<Chunk> = ["без эмбеддинга", "с эмбеддингом"]
+            .into_iter()
+            .map(|text| Chunk::new(doc_id, text.into()))
+            .collect();
+        let (missing, present) = (chunks[0].id, chunks[1].id);
+        let found: Vec<ScoredChunk> = chunks
+            .iter()
+            .zip([0.99, 0.9])
+            .map(|(chunk, score)| ScoredChunk {
+                chunk_id: chunk.id,
+                score,
+            })
+            .collect();
+
+        let mut mock_question_repo = MockQuestionRepo::new();
+        mock_question_repo
+            .expect_read()
+            .returning(|_| Ok(Question::new("Какой?".into())));
+
+        let mut mock_embedding_repo = MockQuestionEmbeddingRepo::new();
+        mock_embedding_repo.expect_read().returning(move |_| {
+            Ok(QuestionEmbending {
+                id: Uuid::new_v4(),
+                question_id,
+                rewrite: None,
+                model_id: "test-model".into(),
+                vec: vec![0.1, 0.2],
+            })
+        });
+
+        let mut mock_vector_searcher = MockVectorSearcher::new();
+        mock_vector_searcher
+            .expect_metric()
+            .returning(|| Metric::Cosine);
+        mock_vector_searcher
+            .expect_search_similar()
+            .returning(move |_, _, _, _| Ok(found.clone()));
+
+        let mut mock_chunk_embending_repo = MockChunkEmbendingRepo::new();
+        mock_chunk_embending_repo
+            .expect_read()
+            .withf(move |chunk_id| *chunk_id == missing)
+            .returning(|_| Err(Error));
+        mock_chunk_embending_repo
+            .expect_read()
+            .returning(|chunk_id| {
+                Ok(ChunkEmbending {
+                    id: Uuid::new_v4(),
+                    chunk_id,
+                    model_id: "test".into(),
+                    vec: vec![0.1, 0.2],
+                    metadata: Default::default(),
+                })
+            });
+
+        let mut mock_chunk_repo = MockChunkRepo::new();
+        mock_chunk_repo.expect_read_many().returning(move |ids| {
+            Ok(chunks
+                .iter()
+                .filter(|c| ids.contains(&c.id))
+                .cloned()
+                .collect())
+        });
+
+        let mut mock_llm = MockLLM::new();
+        mock_llm
+            .expect_model_id()
+            .return_const("test-model".to_string());
+        mock_llm
+            .expect_formulate_unswer()
+            .returning(|_, _, _| Ok("Ответ".into()));
+
+        let mut mock_unswer_repo = MockUnswerRepo::new();
+        mock_unswer_repo
+            .expect_save()
+            .withf(move |u| u.context_chunks_id == vec![present])
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let service = UnswerService::new(
+            Arc::new(mock_llm),
+            Arc::new(mock_unswer_repo),
+            Arc::new(mock_question_repo),
+            Arc::new(mock_embedding_repo),
+            Arc::new(mock_vector_searcher),
+            Arc::new(mock_chunk_repo),
+            Arc::new(tokio::sync::Semaphore::new(5)),
+        )
+        .with_mmr(Arc::new(mock_chunk_embending_repo), 0.5, 2);
+
+        let result = service
+            .get_unswer(&UnswerRequest::new(question_id, 2))
+            .await
+            .unwrap();
+
+        assert_eq!(answer_text(result), "Ответ");
+    }
+
+    #[tokio::test]
+    async fn test_get_unswer_drops_chunks_over_budget() {
+        let found = found(&["короткий", "длинный длинный", "ещё"], &[0.9, 0.9, 0.9]);
+        let kept = vec![found[0].0.id];
+        let dropped = vec![found[1].0.id, found[2].0.id];
+        let mut mocks = UnswerMocks::new("Какой?", found, "Ответ");
+
+        // Один токен на слово
+        let mut mock_tokenizer = MockTokenizer::new();
+        mock_tokenizer
+            .expect_count()
+            .returning(|text| text.split_whitespace().count());
+
+        mocks
+            .llm
+            .expect_formulate_unswer()
+            .withf(|_, context, _| *context == ["короткий"])
+            .times(1)
+            .returning(|_, _, _| Ok("Ответ".into()));
+        mocks
+            .unswer_repo
+            .expect_save()
+            .withf(move |u| {
+                u.context_chunks_id == kept
+                    && u.dropped_chunks_id == dropped
+                    && u.truncated_chunk_id.is_none()
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        // 5 - 1 (ответ) - 1 (инструкции) - 1 (вопрос) = 2 токена на фрагменты
+        let budget = ContextBudget {
+            context_window: 5,
+            answer_tokens: 1,
+            prompt_tokens: 1,
+        };
+        let question_id = mocks.question_id();
+        let service = mocks
+            .service()
+            .with_context_assembler(ContextAssembler::new(Arc::new(mock_tokenizer), budget));
+
+        service
+            .get_unswer(&UnswerRequest::new(question_id, 3))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_unswer_expands_hits_with_neighbours() {
+        let doc_id = Uuid::new_v4();
+        let chunks: Vec<Chunk> = ["a", "b", "c", "d", "e", "f", "g"]
+            .into_iter()
+            .enumerate()
+            .map(|(position, text)| {
+                let mut chunk = Chunk::new(doc_id, text.into());
+                chunk.position = position;
+                chunk
+            })
+            .collect();
+        let found = vec![(chunks[5].clone(), 0.9), (chunks[0].clone(), 0.8)];
+        let expected_ids = vec![chunks[5].id, chunks[0].id];
+        let mut mocks = UnswerMocks::new("Какой?", found, "Ответ");
+
+        mocks
+            .chunk_repo
+            .expect_read_by_doc()
+            .withf(move |id| *id == doc_id)
+            .times(1)
+            .returning(move |_| Ok(chunks.iter().rev().cloned().collect()));
+        mocks
+            .llm
+            .expect_formulate_unswer()
+            .withf(|_, context, _| *context == ["efg", "ab"])
+            .times(1)
+            .returning(|_, _, _| Ok("Ответ".into()));
+        mocks
+            .unswer_repo
+            .expect_save()
+            .withf(move |u| u.context_chunks_id == expected_ids)
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let question_id = mocks.question_id();
+        mocks
+            .service()
+            .with_neighbours(1)
+            .get_unswer(&UnswerRequest::new(question_id, 2))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_unswer_uses_short_parent_document() {
+        let document = Document::new("Rust — язык. Он быстрый.".into());
+        let doc_id = document.id;
+        let found = vec![
+            (Chunk::new(doc_id, "Rust — язык.".into()), 0.9),
+            (Chunk::new(doc_id, "Он быстрый.".into()), 0.8),
+        ];
+        let expected_ids = vec![found[0].0.id];
+        let mut mocks = UnswerMocks::new("Что такое Rust?", found, "Быстрый язык");
+
+        let mut mock_document_repo = MockDocumentRepo::new();
+        mock_document_repo
+            .expect_read()
+            .withf(move |id| *id == doc_id)
+            .times(1)
+            .returning(move |_| Ok(document.clone()));
+
+        mocks
+            .llm
+            .expect_formulate_unswer()
+            .withf(|_, context, _| *context == ["Rust — язык. Он быстрый."])
+            .times(1)
+            .returning(|_, _, _| Ok("Быстрый язык".into()));
+        mocks
+            .unswer_repo
+            .expect_save()
+            .withf(move |u| u.context_chunks_id == expected_ids)
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let question_id = mocks.question_id();
+        let service = mocks
+            .service()
+            .with_parent_documents(Arc::new(mock_document_repo), 1000);
+
+        service
+            .get_unswer(&UnswerRequest::new(question_id, 2))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_unswer_searches_by_rewrites() {
+        let found = found(&["по вопросу", "по переформулировке"], &[0.7, 0.8]);
+        let (by_question, by_rewrite) = (found[0].0.id, found[1].0.id);
+        let mut mocks = UnswerMocks::new("а он быстрый?", found, "Да");
+        mocks.question.rewrites = vec![QueryRewrite {
+            kind: RewriteKind::Standalone,
+            text: "Rust быстрый?".into(),
+        }];
+        mocks.vector = vec![1.0, 0.0];
+        let question_id = mocks.question_id();
+
+        mocks
+            .embedding_repo
+            .expect_read_rewrites()
+            .times(1)
+            .returning(move |_| {
+                Ok(vec![QuestionEmbending {
+                    id: Uuid::new_v4(),
+                    question_id,
+                    rewrite: Some(0),
+                    model_id: "test-model".into(),
+                    vec: vec![0.0, 1.0],
+                }])
+            });
+        mocks
+            .vector_searcher
+            .expect_search_similar()
+            .times(2)
+            .returning(move |vector, _, _, _| {
+                let (chunk_id, score) = if vector[0] > 0.0 {
+                    (by_question, 0.7)
+                } else {
+                    (by_rewrite, 0.8)
+                };
+                Ok(vec![ScoredChunk { chunk_id, score }])
+            });
+
+        mocks
+            .llm
+            .expect_formulate_unswer()
+            .withf(|question, context, _| {
+                question == "а он быстрый?" && *context == ["по переформулировке", "по вопросу"]
+            })
+            .times(1)
+            .returning(|_, _, _| Ok("Да".into()));
+        mocks
+            .unswer_repo
+            .expect_save()
+            .withf(move |u| u.context_chunks_id == vec![by_rewrite, by_question])
+            .times(1)
+            .returning(|_| Ok(()));
+
+        mocks
+            .service()
+            .get_unswer(&UnswerRequest::new(question_id, 2))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stream_unswer_forwards_deltas_and_saves() {
+        let found = found(&["Rust — язык программирования."], &[0.9]);
+        let chunk_id = found[0].0.id;
+        let mut mocks = UnswerMocks::new("Что такое Rust?", found, "");
+
+        mocks.llm.expect_stream_unswer().returning(|_, _, _| {
+            let deltas = vec![Ok("Rust — ".to_string()), Ok("язык [1].".to_string())];
+            Ok(Box::pin(stream::iter(deltas)))
+        });
+        mocks
+            .unswer_repo
+            .expect_save()
+            .withf(move |u| u.text == "Rust — язык [1]." && u.citations[0].chunk_id == chunk_id)
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let question_id = mocks.question_id();
+        let events: Vec<UnswerEvent> = mocks
+            .service()
+            .stream_unswer(&UnswerRequest::new(question_id, 1))
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0], UnswerEvent::Delta("Rust — ".into()));
+        assert!(matches!(
+            &events[2],
+            UnswerEvent::Done { text, citations, .. }
+                if text == "Rust — язык [1]." && citations.len() == 1
+        ));
     }
 
-    
+    #[tokio::test]
+    async fn test_get_unswer_renders_requested_template() {
+        let mut found = found(&["Rust — язык программирования."], &[0.9]);
+        found[0]
+            .0
+            .metadata
+            .insert(TITLE_FIELD.to_string(), "Книга Rust".into());
+        let mut mocks = UnswerMocks::new("Что такое Rust?", found, "");
+        let question_id = mocks.question_id();
+
+        // Запрошенная версия шаблона, а не версия по умолчанию
+        let mut mock_templates = MockPromptTemplateRepo::new();
+        mock_templates
+            .expect_read()
+            .withf(|template_id| template_id == "answer@2")
+            .times(1)
+            .returning(|_| {
+                PromptTemplate::new(
+                    "answer",
+                    2,
+                    "Answer in English.",
+                    "{{titles}}\n{{context}}\nQ: {{question}}",
+                )
+            });
+
+        mocks.llm.expect_formulate_unswer().never();
+        mocks
+            .llm
+            .expect_generate()
+            .withf(|prompt| {
+                prompt.system == "Answer in English."
+                    && prompt.user
+                        == "[1] Книга Rust\n[1] Rust — язык программирования.\nQ: Что такое Rust?"
+            })
+            .times(1)
+            .returning(|_| {
+                Ok(Generation {
+                    text: "Rust is a language [1].".into(),
+                    usage: Some(TokenUsage {
+                        prompt_tokens: 40,
+                        completion_tokens: 7,
+                    }),
+                })
+            });
+
+        // Ответ сохраняется со ссылкой на вопрос и сведениями о генерации
+        mocks
+            .unswer_repo
+            .expect_save()
+            .withf(move |u| {
+                u.question_id == question_id
+                    && u.template_id.as_deref() == Some("answer@2")
+                    && u.model_id == "test-model"
+                    && u.usage.map(|usage| usage.completion_tokens) == Some(7)
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let service = mocks
+            .service()
+            .with_prompt_templates(Arc::new(mock_templates), "answer@1");
+
+        let mut request = UnswerRequest::new(question_id, 1);
+        request.template = Some("answer@2".into());
+        let result = service.get_unswer(&request).await.unwrap();
+
+        assert_eq!(answer_text(result), "Rust is a language [1].");
+    }
+
+    #[tokio::test]
+    async fn test_get_unswer_reports_weak_matches_without_llm() {
+        let (doc_a, doc_b) = (Uuid::new_v4(), Uuid::new_v4());
+        let found = vec![
+            (Chunk::new(doc_a, "Про погоду".into()), 0.3),
+            (Chunk::new(doc_a, "Про дождь".into()), 0.2),
+            (Chunk::new(doc_b, "Про снег".into()), 0.1),
+        ];
+        let mut mocks = UnswerMocks::new("Что такое Rust?", found, "");
+        mocks.llm.expect_formulate_unswer().never();
+        mocks.unswer_repo.expect_save().never();
+
+        let question_id = mocks.question_id();
+        let service = mocks.service().with_no_answer_policy(NoAnswerPolicy {
+            min_score: 0.5,
+            suggestions: 2,
+        });
+
+        let result = service
+            .get_unswer(&UnswerRequest::new(question_id, 3))
+            .await
+            .unwrap();
+
+        let UnswerResult::NotFound { suggestions } = result else {
+            panic!("ожидался NotFound");
+        };
+        let docs: Vec<(Uuid, f32)> = suggestions.iter().map(|s| (s.doc_id, s.score)).collect();
+        assert_eq!(docs, vec![(doc_a, 0.3), (doc_b, 0.1)]);
+    }
+
+    #[tokio::test]
+    async fn test_no_answer_threshold_uses_vector_similarity_with_fusion() {
+        let found = found(&["Rust — язык программирования."], &[0.9]);
+        let mut mocks = UnswerMocks::new("Что такое Rust?", found, "Язык");
+
+        // После слияния RRF оценка около 0.016, но порог сравнивается с близостью 0.9
+        let mut mock_lexical_index = MockLexicalIndex::new();
+        mock_lexical_index
+            .expect_search_text()
+            .returning(|_, _, _| Ok(Vec::new()));
+        mocks
+            .unswer_repo
+            .expect_save()
+            .withf(|u| u.context_scores[0] < 0.1)
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let question_id = mocks.question_id();
+        let service = mocks
+            .service()
+            .with_retrieval_mode(RetrievalMode::Hybrid {
+                lexical_index: Arc::new(mock_lexical_index),
+                fusion: Fusion::ReciprocalRank { k: 60.0 },
+            })
+            .with_no_answer_policy(NoAnswerPolicy {
+                min_score: 0.5,
+                suggestions: 2,
+            });
+
+        let result = service
+            .get_unswer(&UnswerRequest::new(question_id, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(answer_text(result), "Язык");
+    }
+
+    #[tokio::test]
+    async fn test_empty_context_is_refused_without_policy() {
+        let mut mocks = UnswerMocks::new("Что такое Rust?", Vec::new(), "");
+        mocks.llm.expect_formulate_unswer().never();
+        mocks.unswer_repo.expect_save().never();
+
+        let question_id = mocks.question_id();
+        let result = mocks
+            .service()
+            .get_unswer(&UnswerRequest::new(question_id, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result,
+            UnswerResult::NotFound {
+                suggestions: Vec::new()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_unswer_regenerates_ungrounded_answer() {
+        let found = found(&["Rust — язык программирования."], &[0.9]);
+        let mut mocks = UnswerMocks::new("Что такое Rust?", found, "");
+
+        // Первый ответ выдуман, второй опирается на контекст
+        let attempts = std::sync::atomic::AtomicUsize::new(0);
+        mocks
+            .llm
+            .expect_formulate_unswer()
+            .times(2)
+            .returning(move |_, _, _| {
+                let text = match attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                    0 => "Rust создан в 1985 году.",
+                    _ => "Rust — язык программирования [1].",
+                };
+                Ok(Generation {
+                    text: text.into(),
+                    usage: Some(TokenUsage {
+                        prompt_tokens: 40,
+                        completion_tokens: 8,
+                    }),
+                })
+            });
+
+        let mut mock_verifier = MockClaimVerifier::new();
+        mock_verifier
+            .expect_verify()
+            .withf(|_, passages| passages == ["Rust — язык программирования."])
+            .returning(|claims, _| {
+                Ok(claims
+                    .iter()
+                    .map(|claim| if claim.contains("1985") { 0.1 } else { 0.9 })
+                    .collect())
+            });
+
+        mocks
+            .unswer_repo
+            .expect_save()
+            .withf(|u| {
+                u.groundedness == Some(1.0)
+                    && u.unsupported_claims.is_empty()
+                    // Расход учитывает обе попытки
+                    && u.usage
+                        == Some(TokenUsage {
+                            prompt_tokens: 80,
+                            completion_tokens: 16,
+                        })
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let question_id = mocks.question_id();
+        let service = mocks.service().with_verifier(
+            Arc::new(mock_verifier),
+            GroundednessPolicy {
+                min_score: 0.8,
+                retries: 1,
+                refuse: true,
+            },
+        );
+
+        let result = service
+            .get_unswer(&UnswerRequest::new(question_id, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(answer_text(result), "Rust — язык программирования [1].");
+    }
+
+    #[tokio::test]
+    async fn test_get_unswer_continues_conversation() {
+        let found = found(&["В 1.81 появился атрибут expect."], &[0.9]);
+        let mut mocks = UnswerMocks::new("а в 1.81?", found, "");
+        let question_id = mocks.question_id();
+        let mut conversation = Conversation::new();
+        let conversation_id = conversation.id;
+        mocks.question.conversation_id = Some(conversation_id);
+        mocks.question.standalone = Some("Что нового в Rust 1.81?".into());
+        conversation.turns = vec![
+            ConversationTurn {
+                question_id: Uuid::new_v4(),
+                question: "Что нового в Rust 1.80?".into(),
+                unswer_id: Some(Uuid::new_v4()),
+                unswer: Some("Ленивые ячейки.".into()),
+            },
+            ConversationTurn {
+                question_id,
+                question: "а в 1.81?".into(),
+                unswer_id: None,
+                unswer: None,
+            },
+        ];
+
+        // Модель получает исходный вопрос и предыдущий ход разговора
+        mocks
+            .llm
+            .expect_formulate_unswer()
+            .withf(|question, _, history| {
+                question == "а в 1.81?"
+                    && history.len() == 1
+                    && history[0].unswer == "Ленивые ячейки."
+            })
+            .times(1)
+            .returning(|_, _, _| Ok("Атрибут expect [1].".into()));
+
+        // Ответ записывается в ход разговора с этим вопросом
+        let mut mock_conversation_repo = MockConversationRepo::new();
+        mock_conversation_repo.expect_read().returning(move |id| {
+            Ok(Conversation {
+                id,
+                created_at: conversation.created_at,
+                turns: conversation.turns.clone(),
+            })
+        });
+        mock_conversation_repo
+            .expect_update_turn()
+            .withf(move |id, turn| {
+                *id == conversation_id
+                    && turn.question_id == question_id
+                    && turn.question == "а в 1.81?"
+                    && turn.unswer.as_deref() == Some("Атрибут expect [1].")
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let service = mocks.service().with_conversations(
+            Arc::new(mock_conversation_repo),
+            Arc::new(ApproxTokenizer::default()),
+            1000,
+        );
+
+        let result = service
+            .get_unswer(&UnswerRequest::new(question_id, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(answer_text(result), "Атрибут expect [1].");
+    }
+
+    #[tokio::test]
+    async fn test_get_unswer_reuses_cached_answer() {
+        let document = Document::new("Rust — язык программирования.".into());
+        let chunk = Chunk::new(document.id, "Rust — язык программирования.".into());
+        let chunk_id = chunk.id;
+        let similar_id = Uuid::new_v4();
+        let mut cached = Unswer::new(
+            similar_id,
+            "Это язык [1].".into(),
+            &[ContextEntry::from_chunk(chunk.clone(), 0.9)],
+            ScoreKind::Similarity(Metric::Cosine),
+        );
+        cached.created_at = document.updated_at + std::time::Duration::from_secs(1);
+        cached.similar_k = 1;
+        let cached_id = cached.id;
+        let mut mocks = UnswerMocks::new("Rust — это что?", vec![(chunk.clone(), 0.9)], "");
+        let question_id = mocks.question_id();
+
+        // Кэш находит ответ на похожий вопрос, документ с тех пор не менялся
+        let mut mock_similar_questions = MockQuestionEmbeddingRepo::new();
+        mock_similar_questions
+            .expect_search_similar()
+            .returning(move |_, _, _| {
+                Ok(vec![ScoredQuestion {
+                    question_id: similar_id,
+                    score: 0.97,
+                }])
+            });
+        let mut mock_answers = MockUnswerRepo::new();
+        mock_answers
+            .expect_list_by_question()
+            .return_once(move |_| Ok(vec![cached]));
+        let mut mock_chunk_repo = MockChunkRepo::new();
+        mock_chunk_repo
+            .expect_read_many()
+            .returning(move |_| Ok(vec![chunk.clone()]));
+        let mut mock_document_repo = MockDocumentRepo::new();
+        mock_document_repo
+            .expect_read()
+            .returning(move |_| Ok(document.clone()));
+        let cache = UnswerCache::new(
+            Arc::new(mock_similar_questions),
+            Arc::new(mock_answers),
+            Arc::new(mock_chunk_repo),
+            Arc::new(mock_document_repo),
+            0.95,
+        );
+
+        // Ни поиска, ни генерации; копия ответа сохраняется для нового вопроса
+        mocks.vector_searcher.expect_search_similar().never();
+        mocks.llm.expect_formulate_unswer().never();
+        mocks
+            .unswer_repo
+            .expect_save()
+            .withf(move |unswer| {
+                unswer.question_id == question_id
+                    && unswer.cached_from == Some(cached_id)
+                    && unswer.context_chunks_id == vec![chunk_id]
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let result = mocks
+            .service()
+            .with_cache(cache)
+            .get_unswer(&UnswerRequest::new(question_id, 1))
+            .await
+            .unwrap();
+
+        let UnswerResult::Answered(answer) = result else {
+            panic!("ожидался ответ");
+        };
+        assert_ne!(answer.unswer_id, cached_id);
+        assert_eq!(answer.text, "Это язык [1].");
+        assert_eq!(answer.sources[0].chunk_id, chunk_id);
+    }
 }
@@ -0,0 +1,204 @@
+// Заготовки, общие для тестов нескольких модулей
+use std::sync::Arc;
+
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::domain::document::{Chunk, MockChunkRepo};
+use crate::domain::embedding::{
+    Metric, MockQuestionEmbeddingRepo, MockVectorSearcher, QuestionEmbending, ScoredChunk,
+};
+use crate::domain::question::{MockQuestionRepo, Question};
+use crate::domain::unswer::{MockLLM, MockUnswerRepo};
+use crate::service::unswer::UnswerService;
+
+// HTTP-заглушка: на каждое соединение отдаёт очередной ответ и запоминает
+// строку запроса ("POST /v1/graphql HTTP/1.1") и его тело (Null, если тела нет)
+pub async fn serve(
+    responses: Vec<(u16, &'static str)>,
+) -> (String, JoinHandle<Vec<(String, Value)>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let handle = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        for (status, body) in responses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            let body_start = loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                if let Some(i) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break i + 4;
+                }
+            };
+            let head = String::from_utf8_lossy(&request[..body_start]).to_string();
+            let length: usize = head
+                .to_lowercase()
+                .lines()
+                .find_map(|l| l.strip_prefix("content-length:"))
+                .map(|l| l.trim().parse().unwrap())
+                .unwrap_or_default();
+            while request.len() < body_start + length {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let line = head.lines().next().unwrap_or_default().to_string();
+            let json = if length == 0 {
+                Value::Null
+            } else {
+                serde_json::from_slice(&request[body_start..]).unwrap()
+            };
+            requests.push((line, json));
+
+            let content_type = if body.starts_with("data:") {
+                "text/event-stream"
+            } else {
+                "application/json"
+            };
+            let response = format!(
+                "HTTP/1.1 {status} X\r\ncontent-type: {content_type}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            socket.shutdown().await.unwrap();
+        }
+        requests
+    });
+    (url, handle)
+}
+
+pub fn ids(found: &[ScoredChunk]) -> Vec<u128> {
+    found.iter().map(|c| c.chunk_id.as_u128()).collect()
+}
+
+// Детерминированный генератор, чтобы тесты не зависели от rand
+pub fn random_vectors(count: usize, dim: usize, mut seed: u64) -> Vec<Vec<f32>> {
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed % 20_000) as f32 / 10_000.0 - 1.0
+    };
+    (0..count)
+        .map(|_| (0..dim).map(|_| next()).collect())
+        .collect()
+}
+
+// Моки зависимостей UnswerService. Тест задаёт ожидания, которые проверяет, а service()
+// дописывает после них ответы по умолчанию: вопрос question с эмбеддингом vector,
+// поиск находит found с их оценками, модель отвечает reply, ответ сохраняется.
+// mockall перебирает ожидания по порядку, поэтому заданные тестом срабатывают раньше.
+pub struct UnswerMocks {
+    pub question: Question,
+    pub vector: Vec<f32>,
+    pub found: Vec<(Chunk, f32)>,
+    pub reply: String,
+    pub question_repo: MockQuestionRepo,
+    pub embedding_repo: MockQuestionEmbeddingRepo,
+    pub vector_searcher: MockVectorSearcher,
+    pub chunk_repo: MockChunkRepo,
+    pub llm: MockLLM,
+    pub unswer_repo: MockUnswerRepo,
+}
+
+impl UnswerMocks {
+    pub fn new(question: &str, found: Vec<(Chunk, f32)>, reply: &str) -> Self {
+        Self {
+            question: Question::new(question.into()),
+            vector: vec![0.1, 0.2],
+            found,
+            reply: reply.into(),
+            question_repo: MockQuestionRepo::new(),
+            embedding_repo: MockQuestionEmbeddingRepo::new(),
+            vector_searcher: MockVectorSearcher::new(),
+            chunk_repo: MockChunkRepo::new(),
+            llm: MockLLM::new(),
+            unswer_repo: MockUnswerRepo::new(),
+        }
+    }
+
+    pub fn question_id(&self) -> Uuid {
+        self.question.id
+    }
+
+    pub fn service(self) -> UnswerService {
+        self.service_with_question_repos().0
+    }
+
+    // Для сервисов, которые делят с UnswerService репозитории вопросов
+    pub fn service_with_question_repos(
+        mut self,
+    ) -> (
+        UnswerService,
+        Arc<MockQuestionRepo>,
+        Arc<MockQuestionEmbeddingRepo>,
+    ) {
+        let (question_id, vector) = (self.question.id, self.vector.clone());
+        let question = self.question;
+        self.question_repo
+            .expect_read()
+            .return_once(move |_| Ok(question));
+
+        self.embedding_repo.expect_read().returning(move |_| {
+            Ok(QuestionEmbending {
+                id: Uuid::new_v4(),
+                question_id,
+                rewrite: None,
+                model_id: "test-model".into(),
+                vec: vector.clone(),
+            })
+        });
+
+        let found: Vec<ScoredChunk> = self
+            .found
+            .iter()
+            .map(|(chunk, score)| ScoredChunk {
+                chunk_id: chunk.id,
+                score: *score,
+            })
+            .collect();
+        self.vector_searcher
+            .expect_metric()
+            .return_const(Metric::Cosine);
+        self.vector_searcher
+            .expect_search_similar()
+            .returning(move |_, _, _, _| Ok(found.clone()));
+
+        let chunks: Vec<Chunk> = self.found.iter().map(|(chunk, _)| chunk.clone()).collect();
+        self.chunk_repo.expect_read_many().returning(move |ids| {
+            Ok(chunks
+                .iter()
+                .filter(|chunk| ids.contains(&chunk.id))
+                .cloned()
+                .collect())
+        });
+
+        self.llm
+            .expect_model_id()
+            .return_const("test-model".to_string());
+        let reply = self.reply.clone();
+        self.llm
+            .expect_formulate_unswer()
+            .returning(move |_, _, _| Ok(reply.clone().into()));
+
+        self.unswer_repo.expect_save().returning(|_| Ok(()));
+
+        let question_repo = Arc::new(self.question_repo);
+        let embedding_repo = Arc::new(self.embedding_repo);
+        let service = UnswerService::new(
+            Arc::new(self.llm),
+            Arc::new(self.unswer_repo),
+            question_repo.clone(),
+            embedding_repo.clone(),
+            Arc::new(self.vector_searcher),
+            Arc::new(self.chunk_repo),
+            Arc::new(tokio::sync::Semaphore::new(5)),
+        );
+        (service, question_repo, embedding_repo)
+    }
+}